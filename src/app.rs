@@ -1,19 +1,280 @@
-use crate::pattern::Pattern;
+use crate::{
+    config::Config,
+    cost::CostModel,
+    group::{Groups, Overrides},
+    loader::{LoadHandle, LoadProgress},
+    pattern::{Pattern, ReportMeta},
+    query::Query,
+    redact::Redactor,
+    source::{self, SourceLocation},
+    trace::{self, TraceMatch},
+    tree::TreeView,
+    view::{Column, Saved, SortKey, View},
+};
+use crossterm::event::{KeyCode, KeyEvent};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
 use tui::widgets::TableState;
 
-#[derive(Copy, Clone, Debug)]
+/// What is left to do after [`App::handle_key`], by the owner of the terminal
+#[derive(Debug, PartialEq)]
+pub enum KeyOutcome {
+    Handled,
+    Quit,
+    /// Run the editor at the location, out of raw mode
+    OpenEditor(SourceLocation),
+    /// Copy the text to the clipboard, see [`crate::clipboard::copy`]
+    Copy(String),
+}
+
+/// Something a key does, applied with [`App::apply`]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    CancelLoading,
+    NextTab,
+    PreviousTab,
+    NextPattern,
+    PreviousPattern,
+    NextSample,
+    PreviousSample,
+    ScrollDown,
+    ScrollUp,
+    NextTraceMatch,
+    PreviousTraceMatch,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    StartCommand,
+    Copy,
+    ShowTrace,
+    OpenTraceMatch,
+    OpenSource,
+    ToggleReveal,
+    ToggleGroup,
+    ToggleTree,
+    ToggleChart,
+    MarkOrMerge,
+    SplitPattern,
+    ToggleBookmark,
+    NextBookmark,
+    OpenViewMenu,
+    RunCommand,
+    CancelCommand,
+    DeleteCommandChar,
+    NextView,
+    PreviousView,
+    ApplyView,
+    CloseViewMenu,
+}
+
+impl Action {
+    /// What the action does, for lists of the bindings
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::CancelLoading => "Cancel loading the report",
+            Action::NextTab => "Next tab",
+            Action::PreviousTab => "Previous tab",
+            Action::NextPattern => "Next pattern",
+            Action::PreviousPattern => "Previous pattern",
+            Action::NextSample => "Next sample",
+            Action::PreviousSample => "Previous sample",
+            Action::ScrollDown => "Scroll down",
+            Action::ScrollUp => "Scroll up",
+            Action::NextTraceMatch => "Next sample of the trace",
+            Action::PreviousTraceMatch => "Previous sample of the trace",
+            Action::PageDown => "Move one page down",
+            Action::PageUp => "Move one page up",
+            Action::Top => "First row",
+            Action::Bottom => "Last row",
+            Action::StartCommand => "Type a command",
+            Action::Copy => "Copy pattern, rawlog or Detail",
+            Action::ShowTrace => "Show all samples sharing the trace ID",
+            Action::OpenTraceMatch => "Show the trace sample in Detail",
+            Action::OpenSource => "Open the sample's source in the editor",
+            Action::ToggleReveal => "Reveal / redact samples",
+            Action::ToggleGroup => "Expand / collapse a group or tree node",
+            Action::ToggleTree => "Toggle the tree of patterns by field",
+            Action::ToggleChart => "Toggle the volume chart",
+            Action::MarkOrMerge => "Mark a group, on another merge into it",
+            Action::SplitPattern => "Split the pattern out of its group",
+            Action::ToggleBookmark => "Bookmark the pattern or sample",
+            Action::NextBookmark => "Jump to the next bookmark",
+            Action::OpenViewMenu => "Open the menu of saved views",
+            Action::RunCommand => "Run the command",
+            Action::CancelCommand => "Leave the command line",
+            Action::DeleteCommandChar => "Delete the last character",
+            Action::NextView => "Next saved view",
+            Action::PreviousView => "Previous saved view",
+            Action::ApplyView => "Open the selected view",
+            Action::CloseViewMenu => "Close the menu of saved views",
+        }
+    }
+}
+
+/// Where a binding applies
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Every tab
+    Tabs,
+    Tab(MenuItem),
+    /// The command line opened by `:`, unbound chars are typed into it
+    Command,
+    /// The menu of saved views
+    ViewMenu,
+}
+
+/// Key bound to an action
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub scope: Scope,
+    pub key: KeyCode,
+    pub action: Action,
+}
+
+/// Actions of keys. A binding of the current tab wins over one of every tab.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use KeyCode::{Backspace, Char, Down, End, Enter, Esc, Home, Left, Right, Up};
+        let all = Scope::Tabs;
+        let pattern = Scope::Tab(MenuItem::Pattern);
+        let samples = Scope::Tab(MenuItem::Samples);
+        let details = Scope::Tab(MenuItem::Details);
+        let trace = Scope::Tab(MenuItem::Trace);
+        let command = Scope::Command;
+        let menu = Scope::ViewMenu;
+        let bindings = [
+            (all, Char('q'), Action::Quit),
+            (all, Esc, Action::CancelLoading),
+            (all, Right, Action::NextTab),
+            (all, Char('l'), Action::NextTab),
+            (all, Left, Action::PreviousTab),
+            (all, Char('h'), Action::PreviousTab),
+            (all, KeyCode::PageDown, Action::PageDown),
+            (all, KeyCode::PageUp, Action::PageUp),
+            (all, Home, Action::Top),
+            (all, Char('g'), Action::Top),
+            (all, End, Action::Bottom),
+            (all, Char('G'), Action::Bottom),
+            (all, Char(':'), Action::StartCommand),
+            (all, Char('y'), Action::Copy),
+            (all, Char('t'), Action::ShowTrace),
+            (all, Char('o'), Action::OpenSource),
+            (all, Char('R'), Action::ToggleReveal),
+            (all, Char('z'), Action::ToggleGroup),
+            (all, Char('T'), Action::ToggleTree),
+            (all, Char('c'), Action::ToggleChart),
+            (all, Char('m'), Action::MarkOrMerge),
+            (all, Char('x'), Action::SplitPattern),
+            (all, Char('b'), Action::ToggleBookmark),
+            (all, Char('\''), Action::NextBookmark),
+            (all, Char('v'), Action::OpenViewMenu),
+            (pattern, Down, Action::NextPattern),
+            (pattern, Char('j'), Action::NextPattern),
+            (pattern, Up, Action::PreviousPattern),
+            (pattern, Char('k'), Action::PreviousPattern),
+            (pattern, Enter, Action::ToggleGroup),
+            (samples, Down, Action::NextSample),
+            (samples, Char('j'), Action::NextSample),
+            (samples, Up, Action::PreviousSample),
+            (samples, Char('k'), Action::PreviousSample),
+            (samples, Char('d'), Action::NextTab),
+            (details, Down, Action::ScrollDown),
+            (details, Char('j'), Action::ScrollDown),
+            (details, Char('d'), Action::ScrollDown),
+            (details, Up, Action::ScrollUp),
+            (details, Char('k'), Action::ScrollUp),
+            (trace, Down, Action::NextTraceMatch),
+            (trace, Char('j'), Action::NextTraceMatch),
+            (trace, Up, Action::PreviousTraceMatch),
+            (trace, Char('k'), Action::PreviousTraceMatch),
+            (trace, Enter, Action::OpenTraceMatch),
+            (trace, Char('d'), Action::OpenTraceMatch),
+            (command, Enter, Action::RunCommand),
+            (command, Esc, Action::CancelCommand),
+            (command, Backspace, Action::DeleteCommandChar),
+            (menu, Down, Action::NextView),
+            (menu, Char('j'), Action::NextView),
+            (menu, Up, Action::PreviousView),
+            (menu, Char('k'), Action::PreviousView),
+            (menu, Enter, Action::ApplyView),
+            (menu, Esc, Action::CloseViewMenu),
+            (menu, Char('v'), Action::CloseViewMenu),
+            (menu, Char('q'), Action::CloseViewMenu),
+        ];
+        Keymap {
+            bindings: bindings
+                .iter()
+                .map(|&(scope, key, action)| Binding { scope, key, action })
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Action of `key` in `scope`, a tab falls back to the bindings of
+    /// every tab
+    pub fn action(&self, scope: Scope, key: KeyCode) -> Option<Action> {
+        let bound = |wanted: Scope| {
+            self.bindings
+                .iter()
+                .find(|binding| binding.scope == wanted && binding.key == key)
+                .map(|binding| binding.action)
+        };
+        match scope {
+            Scope::Tab(_) => bound(scope).or_else(|| bound(Scope::Tabs)),
+            _ => bound(scope),
+        }
+    }
+
+    /// Bind `key` to `action` in `scope`, replacing what the key did there
+    pub fn bind(&mut self, scope: Scope, key: KeyCode, action: Action) {
+        self.unbind(scope, key);
+        self.bindings.push(Binding { scope, key, action });
+    }
+
+    /// Remove the binding of `key` in `scope`
+    pub fn unbind(&mut self, scope: Scope, key: KeyCode) {
+        self.bindings
+            .retain(|binding| binding.scope != scope || binding.key != key);
+    }
+
+    /// Every binding, in the order they were bound
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum MenuItem {
     Pattern,
     Samples,
     Details,
+    Trace,
 }
+/// Tab at an index, indexes past the last tab are clamped to it
 impl From<usize> for MenuItem {
     fn from(input: usize) -> MenuItem {
         match input {
             0 => MenuItem::Pattern,
             1 => MenuItem::Samples,
             2 => MenuItem::Details,
-            _ => todo!(),
+            _ => MenuItem::Trace,
         }
     }
 }
@@ -24,6 +285,7 @@ impl From<MenuItem> for usize {
             MenuItem::Pattern => 0,
             MenuItem::Samples => 1,
             MenuItem::Details => 2,
+            MenuItem::Trace => 3,
             //  _ => 2,
         }
     }
@@ -32,6 +294,13 @@ impl From<MenuItem> for usize {
 pub enum Event<I> {
     Input(I),
     Tick,
+    /// A batch of patterns parsed by the loader
+    Patterns(Vec<Pattern>),
+    Progress(LoadProgress),
+    /// Metadata of a report loaded from an envelope
+    Report(ReportMeta),
+    /// The loader is done, with the error that stopped it if any
+    Loaded(Option<String>),
 }
 
 pub struct TabsState<'a> {
@@ -40,7 +309,7 @@ pub struct TabsState<'a> {
 }
 
 impl<'a> TabsState<'a> {
-    pub fn new(titles: Vec<&'a str>) -> TabsState {
+    pub fn new(titles: Vec<&'a str>) -> TabsState<'a> {
         TabsState { titles, index: 0 }
     }
     pub fn next(&mut self) {
@@ -56,53 +325,740 @@ impl<'a> TabsState<'a> {
     }
 }
 
+/// Sample selection and Detail scroll remembered for one pattern
+#[derive(Clone, Copy, Default)]
+pub struct PatternView {
+    /// Index into the samples of the pattern, rows change with the filter
+    pub selected_sample: Option<usize>,
+    pub scroll: u16,
+}
+
+/// Row of the Pattern table standing for a group of similar patterns
+#[derive(Copy, Clone, Debug)]
+pub struct GroupRow {
+    /// Patterns in the group matching the filter
+    pub members: usize,
+    pub count: usize,
+    pub percent: f32,
+    pub cost: f64,
+    pub expanded: bool,
+}
+
 /// App holds the state of the application
 pub struct App<'a> {
-    /// All patterns for logs
+    /// All patterns for logs, in the order they were loaded
     pub patterns: Vec<Pattern>,
+    /// Indexes into `patterns` of the rows of the Pattern table
+    pub pattern_rows: Vec<usize>,
+    /// Number of patterns matching the filter, grouped or not
+    pub matching_patterns: usize,
+    /// Indexes into `patterns` of the patterns matching the filter, highest
+    /// count first
+    pub ranked_patterns: Vec<usize>,
+    /// Indexes into `patterns` of the patterns matching the filter in the
+    /// order of the table, members of collapsed groups included
+    table_patterns: Vec<usize>,
+    /// Whether the Pattern tab shows the volume chart instead of samples
+    pub show_chart: bool,
+    /// Estimates the monthly cost of patterns, `None` without prices
+    pub cost: Option<CostModel>,
+    /// Estimated monthly cost of each pattern, 0 without prices
+    pub costs: Vec<f64>,
+    /// Bytes per line of each pattern priced so far, kept as measuring them
+    /// scans the samples
+    line_bytes: Vec<f64>,
+    /// Estimated monthly cost of the patterns matching the filter
+    pub total_cost: f64,
+    /// Order of the rows of the Pattern table
+    pub sort: SortKey,
+    /// Optional columns shown in the Pattern table
+    pub columns: Vec<Column>,
+    /// Saved views and bookmarks
+    pub saved: Saved,
+    /// Where `saved` is written, `None` to keep it in memory
+    pub saved_path: Option<PathBuf>,
+    /// Selection in the menu of saved views, `None` when it's closed
+    pub view_menu: Option<TableState>,
+    /// Similar patterns, shown as one row per group
+    pub groups: Groups,
+    /// Rows leading a group, keyed by index in `patterns`
+    pub group_rows: HashMap<usize, GroupRow>,
+    /// Patterns shown nested under the row of their group
+    pub nested_rows: HashSet<usize>,
+    /// Groups shown with all their patterns
+    pub expanded_groups: HashSet<usize>,
+    /// Pattern whose group is merged into the next one marked
+    pub marked_pattern: Option<usize>,
+    /// Tree of patterns by sample fields shown instead of the Pattern
+    /// table, `None` when showing the table
+    pub tree: Option<TreeView>,
+    /// Index into `patterns` by pattern text, to merge patterns of several apps
+    pub merge_index: HashMap<String, usize>,
     pub title: &'a str,
     pub tabs: TabsState<'a>,
     pub pattern_table_state: TableState,
+    /// First pattern row on screen, kept by the ui
+    pub pattern_table_offset: usize,
+    /// Indexes into the samples of the selected pattern of the rows of the
+    /// Sample table
+    pub sample_rows: Vec<usize>,
+    /// Sample selection of the selected pattern
     pub sample_table_state: TableState,
-    pub active_menu_item: MenuItem,
+    /// First sample row on screen, kept by the ui
+    pub sample_table_offset: usize,
+    /// Actions of the keys
+    pub keymap: Keymap,
+    /// Pretty printed rawlog of the selected sample
     pub current_rawlog: String,
+    /// Detail scroll of the selected pattern
     pub scroll: u16,
+    /// Views of patterns visited before, keyed by index in `patterns`
+    pub pattern_views: HashMap<usize, PatternView>,
+    /// Rows visible in the pattern table, updated on every draw
+    pub pattern_page_size: usize,
+    /// Rows visible in the sample table, updated on every draw
+    pub sample_page_size: usize,
+    /// Lines visible in the detail view, updated on every draw
+    pub detail_page_size: u16,
+    /// Largest useful detail scroll offset, updated on every draw
+    pub detail_max_scroll: u16,
+    /// Command line typed after `:`, `None` when not editing
+    pub command: Option<String>,
+    /// Trace ID shown in the Trace tab
+    pub trace_id: Option<String>,
+    /// Samples sharing `trace_id`, ordered by date
+    pub trace_matches: Vec<TraceMatch>,
+    pub trace_table_state: TableState,
+    /// First trace row on screen, kept by the ui
+    pub trace_table_offset: usize,
+    /// Rows visible in the trace table, updated on every draw
+    pub trace_page_size: usize,
+    /// Message shown in the status line until the next key press
+    pub status: Option<String>,
+    /// Progress of the report still loading, `None` once loaded
+    pub loading: Option<LoadProgress>,
+    /// Loader of the report, used to cancel it
+    pub loader: Option<LoadHandle>,
+    /// Error that stopped loading the report
+    pub load_error: Option<String>,
+    /// Metadata of the loaded reports that have any
+    pub reports: Vec<ReportMeta>,
+    /// Query patterns and samples must match to be shown
+    pub filter: Option<Query>,
+    /// Redacts rawlogs before they are shown, copied or saved
+    pub redactor: Redactor,
+    /// Whether rawlogs are shown without redaction
+    pub reveal: bool,
+    pub config: Config,
 }
 
 impl<'a> App<'a> {
-    pub fn new(title: &'a str, patterns: Vec<Pattern>) -> App<'a> {
-        let tabs = TabsState::new(vec!["Pattern", "Sample", "Detail"]);
-        let mut pattern_table_state = TableState::default();
-        let mut sample_table_state = TableState::default();
-        pattern_table_state.select(Some(0));
-        sample_table_state.select(Some(0));
-        let active_menu_item = MenuItem::Pattern;
-        App {
-            patterns,
+    pub fn new(title: &'a str, patterns: Vec<Pattern>, config: Config) -> App<'a> {
+        let tabs = TabsState::new(vec!["Pattern", "Sample", "Detail", "Trace"]);
+        let pattern_table_state = TableState::default();
+        let sample_table_state = TableState::default();
+        let mut app = App {
+            patterns: Vec::new(),
+            pattern_rows: Vec::new(),
+            matching_patterns: 0,
+            ranked_patterns: Vec::new(),
+            table_patterns: Vec::new(),
+            show_chart: false,
+            cost: CostModel::new(&config.cost),
+            costs: Vec::new(),
+            line_bytes: Vec::new(),
+            total_cost: 0.0,
+            sort: SortKey::default(),
+            columns: Column::ALL.to_vec(),
+            saved: Saved::default(),
+            saved_path: None,
+            view_menu: None,
+            groups: Groups::new(config.group.clone(), Overrides::default(), None),
+            group_rows: HashMap::new(),
+            nested_rows: HashSet::new(),
+            expanded_groups: HashSet::new(),
+            marked_pattern: None,
+            tree: None,
+            merge_index: HashMap::new(),
             title,
             tabs,
             pattern_table_state,
+            pattern_table_offset: 0,
+            sample_rows: Vec::new(),
             sample_table_state,
-            active_menu_item,
+            sample_table_offset: 0,
+            keymap: Keymap::default(),
             current_rawlog: String::new(),
             scroll: 0,
+            pattern_views: HashMap::new(),
+            pattern_page_size: 1,
+            sample_page_size: 1,
+            detail_page_size: 1,
+            detail_max_scroll: 0,
+            command: None,
+            trace_id: None,
+            trace_matches: Vec::new(),
+            trace_table_state: TableState::default(),
+            trace_table_offset: 0,
+            trace_page_size: 1,
+            status: None,
+            loading: None,
+            loader: None,
+            load_error: None,
+            reports: Vec::new(),
+            filter: None,
+            redactor: Redactor::new(&config.redact).expect("redaction is checked on config load"),
+            reveal: false,
+            config,
+        };
+        app.push_patterns(patterns);
+        app
+    }
+
+    /// Stop loading the report, the patterns loaded so far are kept
+    pub fn cancel_loading(&mut self) {
+        if let Some(loader) = &self.loader {
+            loader.cancel();
         }
     }
+
+    /// The loader is done, `error` tells why it stopped early
+    pub fn finish_loading(&mut self, error: Option<String>) {
+        self.loading = None;
+        self.loader = None;
+        if let Some(error) = error {
+            self.status = Some(format!("Failed to load report: {error}"));
+            self.load_error = Some(error);
+        }
+    }
+
+    /// Add loaded patterns, keeping the Pattern table ordered by count and
+    /// the selected pattern selected. Patterns of several apps with the
+    /// same text are merged into one.
+    pub fn push_patterns(&mut self, patterns: Vec<Pattern>) {
+        if patterns.is_empty() {
+            return;
+        }
+        let selected = self.current_pattern_index();
+        let mut merged = Vec::new();
+        for mut pattern in patterns {
+            if pattern.app.is_some() {
+                pattern.share_by_app();
+                if let Some(&index) = self.merge_index.get(&pattern.patterns) {
+                    self.patterns[index].merge(pattern);
+                    merged.push(index);
+                    continue;
+                }
+                self.merge_index
+                    .insert(pattern.patterns.clone(), self.patterns.len());
+            }
+            self.patterns.push(pattern);
+        }
+        self.groups.update(&self.patterns);
+        self.calculate_percent();
+        self.calculate_costs(&merged);
+        self.rebuild_rows();
+        self.reselect_pattern(selected);
+        self.refresh_tree();
+    }
+
+    /// Show only the patterns and samples matching `filter`, or all of them
+    pub fn set_filter(&mut self, filter: Option<Query>) {
+        let selected = self.current_pattern_index();
+        self.filter = filter;
+        self.rebuild_rows();
+        self.reselect_pattern(selected);
+        self.refresh_tree();
+    }
+
+    /// Select the row of `patterns[selected]` again after rows changed, the
+    /// row of its group when it's collapsed, or the first row when it has
+    /// no row anymore
+    fn reselect_pattern(&mut self, selected: Option<usize>) {
+        let row = selected.and_then(|selected| {
+            let group = self.groups.group(selected);
+            self.pattern_rows
+                .iter()
+                .position(|&i| i == selected)
+                .or_else(|| {
+                    self.pattern_rows
+                        .iter()
+                        .position(|&i| self.groups.group(i) == group)
+                })
+        });
+        match row {
+            Some(row) if Some(self.pattern_rows[row]) == selected => {
+                self.pattern_table_state.select(Some(row));
+                self.refresh_samples();
+            }
+            row => {
+                self.pattern_table_state.select(None);
+                let row = row.unwrap_or(0);
+                self.change_pattern(|state, len| select_clamped(state, len, row));
+            }
+        }
+    }
+
+    /// Rebuild the rows of the Pattern table from the patterns matching the
+    /// filter. Each group gets one row, led by its pattern with the highest
+    /// count and followed by the others when expanded; rows are ordered by
+    /// the count of their group.
+    fn rebuild_rows(&mut self) {
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            if self.filter.as_ref().is_none_or(|f| f.matches(pattern)) {
+                groups
+                    .entry(self.groups.group(index))
+                    .or_default()
+                    .push(index);
+            }
+        }
+        let patterns = &self.patterns;
+        let mut groups: Vec<(usize, Vec<usize>, usize)> = groups
+            .into_iter()
+            .map(|(group, mut members)| {
+                members.sort_by_key(|&i| (Reverse(patterns[i].count), i));
+                let count = members.iter().map(|&i| patterns[i].count).sum();
+                (group, members, count)
+            })
+            .collect();
+        let costs = &self.costs;
+        let group_cost = |members: &[usize]| members.iter().map(|&i| costs[i]).sum::<f64>();
+        match self.sort {
+            SortKey::Count => {
+                groups.sort_by_key(|(_, members, count)| (Reverse(*count), members[0]))
+            }
+            SortKey::Pattern => groups.sort_by(|(_, a, _), (_, b, _)| {
+                patterns[a[0]].patterns.cmp(&patterns[b[0]].patterns)
+            }),
+            SortKey::Cost => groups.sort_by(|(_, a, _), (_, b, _)| {
+                group_cost(b)
+                    .total_cmp(&group_cost(a))
+                    .then(a[0].cmp(&b[0]))
+            }),
+        }
+
+        self.pattern_rows.clear();
+        self.group_rows.clear();
+        self.nested_rows.clear();
+        self.table_patterns.clear();
+        self.matching_patterns = 0;
+        self.total_cost = 0.0;
+        for (group, members, count) in groups {
+            self.matching_patterns += members.len();
+            self.table_patterns.extend(&members);
+            let cost = group_cost(&members);
+            self.total_cost += cost;
+            self.pattern_rows.push(members[0]);
+            if members.len() == 1 {
+                continue;
+            }
+            let expanded = self.expanded_groups.contains(&group);
+            let percent = members
+                .iter()
+                .map(|&i| patterns[i].percent.unwrap_or(0.0))
+                .sum();
+            self.group_rows.insert(
+                members[0],
+                GroupRow {
+                    members: members.len(),
+                    count,
+                    percent,
+                    cost,
+                    expanded,
+                },
+            );
+            if expanded {
+                self.pattern_rows.extend_from_slice(&members[1..]);
+                self.nested_rows.extend(&members[1..]);
+            }
+        }
+        self.ranked_patterns.clone_from(&self.table_patterns);
+        self.ranked_patterns
+            .sort_by_key(|&i| (Reverse(patterns[i].count), i));
+    }
+
+    /// Price the patterns by their counts, measuring the lines of new
+    /// patterns and of the `merged` ones whose samples changed
+    fn calculate_costs(&mut self, merged: &[usize]) {
+        let cost = match &self.cost {
+            Some(cost) => cost,
+            None => {
+                self.costs = vec![0.0; self.patterns.len()];
+                return;
+            }
+        };
+        for &index in merged {
+            if index < self.line_bytes.len() {
+                self.line_bytes[index] = cost.bytes_per_line(&self.patterns[index]);
+            }
+        }
+        for pattern in &self.patterns[self.line_bytes.len()..] {
+            self.line_bytes.push(cost.bytes_per_line(pattern));
+        }
+        self.costs = self
+            .patterns
+            .iter()
+            .zip(&self.line_bytes)
+            .map(|(pattern, &bytes)| cost.cost_of(pattern.count, bytes))
+            .collect();
+    }
+
+    /// Order the Pattern table by `sort`
+    pub fn set_sort(&mut self, sort: SortKey) {
+        let selected = self.current_pattern_index();
+        self.sort = sort;
+        self.rebuild_rows();
+        self.reselect_pattern(selected);
+    }
+
+    /// The current filter, sort, columns and tab as a view called `name`
+    pub fn current_view(&self, name: &str) -> View {
+        View {
+            name: name.to_string(),
+            filter: self.filter.as_ref().map(ToString::to_string),
+            sort: self.sort,
+            columns: self.columns.clone(),
+            tab: self.current_menu_item(),
+        }
+    }
+
+    /// Apply the filter, sort, columns and tab of `view`
+    pub fn apply_view(&mut self, view: &View) {
+        let filter = match view.filter.as_deref().map(Query::parse).transpose() {
+            Ok(filter) => filter,
+            Err(e) => {
+                self.status = Some(format!("Invalid filter of view {}: {e}", view.name));
+                return;
+            }
+        };
+        self.sort = view.sort;
+        self.columns = view.columns.clone();
+        self.set_filter(filter);
+        self.select_tab(view.tab);
+        self.status = Some(format!("View {}", view.name));
+    }
+
+    /// Write the saved views and bookmarks, `done` is the status on success
+    fn store_saved(&mut self, done: String) {
+        let result = match &self.saved_path {
+            Some(path) => self.saved.save(path),
+            None => Ok(()),
+        };
+        self.status = Some(match result {
+            Ok(()) => done,
+            Err(e) => format!("{done}, but not saved: {e}"),
+        });
+    }
+
+    /// Run `:view <name>`, `:view save <name>` or `:view delete <name>`
+    fn run_view(&mut self, args: &str) {
+        let (action, name) = match args.split_once(' ') {
+            Some((action, name)) if action == "save" || action == "delete" => (action, name.trim()),
+            _ => ("apply", args),
+        };
+        if name.is_empty() {
+            self.open_view_menu();
+            return;
+        }
+        match action {
+            "save" => {
+                let view = self.current_view(name);
+                self.saved.put_view(view);
+                self.store_saved(format!("Saved view {name}"));
+            }
+            "delete" => {
+                if self.saved.remove_view(name) {
+                    self.store_saved(format!("Deleted view {name}"));
+                } else {
+                    self.status = Some(format!("No view named {name}"));
+                }
+            }
+            _ => match self.saved.view(name).cloned() {
+                Some(view) => self.apply_view(&view),
+                None => self.status = Some(format!("No view named {name}")),
+            },
+        }
+    }
+
+    /// Show the menu of saved views
+    pub fn open_view_menu(&mut self) {
+        if self.saved.views.is_empty() {
+            self.status = Some("No saved views, save one with :view save <name>".to_string());
+            return;
+        }
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.view_menu = Some(state);
+    }
+
+    /// Move the selection of the view menu down, or up for a negative `delta`
+    pub fn move_view_menu(&mut self, delta: isize) {
+        let len = self.saved.views.len();
+        if let Some(state) = &mut self.view_menu {
+            if delta > 0 {
+                next_wrapping(state, len);
+            } else {
+                previous_wrapping(state, len);
+            }
+        }
+    }
+
+    /// Apply the view selected in the menu and close it
+    pub fn apply_menu_view(&mut self) {
+        let selected = self.view_menu.take().and_then(|state| state.selected());
+        if let Some(view) = selected.and_then(|i| self.saved.views.get(i)).cloned() {
+            self.apply_view(&view);
+        }
+    }
+
+    /// Bookmark the selected pattern in the Pattern tab, or the selected
+    /// sample in the others, or remove the bookmark
+    pub fn toggle_bookmark(&mut self) {
+        let index = match self.current_pattern_index() {
+            Some(index) => index,
+            None => return,
+        };
+        let pattern = &self.patterns[index];
+        let done = match self.current_menu_item() {
+            MenuItem::Pattern => match self.saved.bookmarks.toggle_pattern(pattern) {
+                true => "Bookmarked the pattern",
+                false => "Removed the bookmark of the pattern",
+            },
+            _ => {
+                let sample = match self.current_sample_index() {
+                    Some(sample) => &pattern.samples()[sample],
+                    None => return,
+                };
+                match self.saved.bookmarks.toggle_sample(pattern, sample) {
+                    true => "Bookmarked the sample",
+                    false => "Removed the bookmark of the sample",
+                }
+            }
+        };
+        self.store_saved(done.to_string());
+    }
+
+    /// Select the next bookmarked pattern in the Pattern tab, or the next
+    /// bookmarked sample of the pattern in the others
+    pub fn next_bookmark(&mut self) {
+        let bookmarks = &self.saved.bookmarks;
+        match self.current_menu_item() {
+            MenuItem::Pattern => {
+                let current = self.current_pattern_index();
+                let start = current
+                    .and_then(|c| self.table_patterns.iter().position(|&i| i == c))
+                    .map_or(0, |row| row + 1);
+                let len = self.table_patterns.len();
+                let next = (0..len)
+                    .map(|offset| self.table_patterns[(start + offset) % len])
+                    .find(|&i| bookmarks.has_pattern(&self.patterns[i]));
+                match next {
+                    Some(index) => {
+                        self.select_pattern(index);
+                    }
+                    None => self.status = Some("No bookmarked pattern".to_string()),
+                }
+            }
+            _ => {
+                let pattern = match self.current_pattern() {
+                    Some(pattern) => pattern,
+                    None => return,
+                };
+                let samples = pattern.samples();
+                let start = self.sample_table_state.selected().map_or(0, |row| row + 1);
+                let len = self.sample_rows.len();
+                let next = (0..len)
+                    .map(|offset| (start + offset) % len)
+                    .find(|&row| bookmarks.has_sample(pattern, &samples[self.sample_rows[row]]));
+                match next {
+                    Some(row) => self.change_sample(|state, len| select_clamped(state, len, row)),
+                    None => self.status = Some("No bookmarked sample".to_string()),
+                }
+            }
+        }
+    }
+
+    /// Show the volume chart or the samples below the Pattern table
+    pub fn toggle_chart(&mut self) {
+        self.show_chart = !self.show_chart;
+    }
+
+    /// Show the tree of patterns by `fields` instead of the Pattern table,
+    /// or by the configured fields when none are given
+    pub fn show_tree(&mut self, fields: Vec<String>) {
+        let fields = if fields.is_empty() {
+            self.config.tree.fields.clone()
+        } else {
+            fields
+        };
+        self.tree = Some(TreeView::new(fields, &self.config.source));
+        self.refresh_tree();
+    }
+
+    /// Switch between the Pattern table and the tree by the configured fields
+    pub fn toggle_tree(&mut self) {
+        if self.tree.take().is_none() {
+            self.show_tree(Vec::new());
+        }
+    }
+
+    /// Rebuild the tree from the patterns matching the filter
+    fn refresh_tree(&mut self) {
+        let (patterns, filter) = (&self.patterns, &self.filter);
+        if let Some(tree) = &mut self.tree {
+            let visible: Vec<usize> = (0..patterns.len())
+                .filter(|&i| filter.as_ref().is_none_or(|f| f.matches(&patterns[i])))
+                .collect();
+            tree.rebuild(patterns, &visible);
+        }
+    }
+
+    /// Apply `change` to the tree selection, selecting the pattern of a
+    /// pattern row so its samples are shown
+    fn change_tree(&mut self, change: impl FnOnce(&mut TableState, usize)) {
+        let pattern = match &mut self.tree {
+            Some(tree) => {
+                change(&mut tree.state, tree.rows.len());
+                tree.selected().and_then(|row| row.pattern)
+            }
+            None => return,
+        };
+        if let Some(pattern) = pattern {
+            self.select_pattern(pattern);
+        }
+    }
+
+    /// Select the row of `patterns[index]`, expanding its group when it's
+    /// collapsed. `false` when the filter hides it.
+    fn select_pattern(&mut self, index: usize) -> bool {
+        let group = self.groups.group(index);
+        if !self.pattern_rows.contains(&index) && self.expanded_groups.insert(group) {
+            let selected = self.current_pattern_index();
+            self.rebuild_rows();
+            self.reselect_pattern(selected);
+        }
+        match self.pattern_rows.iter().position(|&i| i == index) {
+            Some(row) => {
+                self.change_pattern(|state, len| select_clamped(state, len, row));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Expand or collapse the selected node of the tree, or the group of
+    /// the selected pattern in the Pattern table
+    pub fn toggle_group(&mut self) {
+        if let Some(tree) = &mut self.tree {
+            if tree.toggle_selected() {
+                self.refresh_tree();
+            }
+            return;
+        }
+        let selected = match self.current_pattern_index() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let group = self.groups.group(selected);
+        if !self.expanded_groups.remove(&group) {
+            if !self.group_rows.contains_key(&selected) {
+                self.status = Some("The pattern has no similar patterns".to_string());
+                return;
+            }
+            self.expanded_groups.insert(group);
+        }
+        self.rebuild_rows();
+        self.reselect_pattern(Some(selected));
+    }
+
+    /// Mark the group of the selected pattern, or merge the marked group
+    /// into the group of the selected pattern
+    pub fn mark_or_merge(&mut self) {
+        let selected = match self.current_pattern_index() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let marked = match self.marked_pattern.take() {
+            Some(marked) => marked,
+            None => {
+                self.marked_pattern = Some(selected);
+                self.status =
+                    Some("Marked, press 'm' on another pattern to merge the groups".to_string());
+                return;
+            }
+        };
+        if self.groups.group(marked) == self.groups.group(selected) {
+            self.status = Some("Unmarked".to_string());
+            return;
+        }
+        let result = self.groups.merge(marked, selected);
+        self.expanded_groups.insert(self.groups.group(selected));
+        self.rebuild_rows();
+        self.reselect_pattern(Some(selected));
+        self.status = Some(match result {
+            Ok(()) => format!(
+                "Merged into a group of {} patterns",
+                self.groups.size(selected)
+            ),
+            Err(e) => format!("Merged, but not saved: {e}"),
+        });
+    }
+
+    /// Take the selected pattern out of its group
+    pub fn split_pattern(&mut self) {
+        let selected = match self.current_pattern_index() {
+            Some(selected) => selected,
+            None => return,
+        };
+        if self.groups.size(selected) == 1 {
+            self.status = Some("The pattern is not in a group".to_string());
+            return;
+        }
+        let result = self.groups.split(selected);
+        self.rebuild_rows();
+        self.reselect_pattern(Some(selected));
+        self.status = Some(match result {
+            Ok(()) => "Split the pattern out of its group".to_string(),
+            Err(e) => format!("Split, but not saved: {e}"),
+        });
+    }
+
     pub fn on_right(&mut self) {
         self.tabs.next();
     }
 
     pub fn calculate_percent(&mut self) {
-        // get total count from patterns
+        // get total count from patterns, and per app for merged patterns
         let mut total = 0;
+        let mut app_totals: HashMap<&str, usize> = HashMap::new();
         for pattern in &self.patterns {
             total += pattern.count;
+            for share in &pattern.apps {
+                *app_totals.entry(&share.app).or_default() += share.count;
+            }
         }
+        let app_totals: HashMap<String, usize> = app_totals
+            .into_iter()
+            .map(|(app, total)| (app.to_string(), total))
+            .collect();
 
         // calculate percent
         for pattern in self.patterns.iter_mut() {
-            let percent = (pattern.count as f32 / total as f32) * 100.0;
+            let percent = if total == 0 {
+                0.0
+            } else {
+                (pattern.count as f32 / total as f32) * 100.0
+            };
             pattern.percent = Some(percent);
+            for share in pattern.apps.iter_mut() {
+                let app_total = app_totals.get(&share.app).copied().unwrap_or(0);
+                share.percent = Some(if app_total == 0 {
+                    0.0
+                } else {
+                    (share.count as f32 / app_total as f32) * 100.0
+                });
+            }
         }
     }
 
@@ -114,56 +1070,232 @@ impl<'a> App<'a> {
         self.tabs.index.into()
     }
 
+    pub fn select_tab(&mut self, item: MenuItem) {
+        self.tabs.index = item.into();
+    }
+
+    /// Number of samples shown of the selected pattern, 0 when there is none
     pub fn current_amount_samples(&self) -> usize {
-        self.patterns[self.pattern_table_state.selected().unwrap()]
-            .samples
-            .len()
+        self.sample_rows.len()
+    }
+
+    /// Indexes of the samples of the selected pattern matching the filter
+    fn visible_samples(&self) -> Vec<usize> {
+        let pattern = match self.current_pattern() {
+            Some(pattern) => pattern,
+            None => return Vec::new(),
+        };
+        let samples = pattern.samples();
+        match &self.filter {
+            Some(filter) => (0..samples.len())
+                .filter(|&i| filter.matches_sample(pattern, &samples[i]))
+                .collect(),
+            None => (0..samples.len()).collect(),
+        }
+    }
+
+    /// Update the sample rows of the selected pattern, keeping the selected
+    /// sample selected when it's still shown
+    fn refresh_samples(&mut self) {
+        let selected = self.current_sample_index();
+        self.sample_rows = self.visible_samples();
+        let row = selected
+            .and_then(|selected| self.sample_rows.iter().position(|&i| i == selected))
+            .unwrap_or(0);
+        select_clamped(&mut self.sample_table_state, self.sample_rows.len(), row);
+        if self.current_sample_index() != selected {
+            self.refresh_detail();
+        }
+    }
+
+    /// Index into the samples of the selected pattern of the selected sample
+    pub fn current_sample_index(&self) -> Option<usize> {
+        let selected = self.sample_table_state.selected()?;
+        self.sample_rows.get(selected).copied()
     }
 
     pub fn handle_down_patterns(&mut self) {
-        if let Some(selected) = self.pattern_table_state.selected() {
-            let amount_patterns = self.patterns.len();
-            if selected >= amount_patterns - 1 {
-                self.pattern_table_state.select(Some(0));
-            } else {
-                self.pattern_table_state.select(Some(selected + 1));
-            }
+        if self.tree.is_some() {
+            self.change_tree(next_wrapping);
+        } else {
+            self.change_pattern(next_wrapping);
         }
     }
     pub fn handle_down_samples(&mut self) {
-        let current_amount_samples = self.current_amount_samples();
-        if let Some(selected) = self.sample_table_state.selected() {
-            if selected >= current_amount_samples - 1 {
-                self.sample_table_state.select(Some(0));
-            } else {
-                self.sample_table_state.select(Some(selected + 1));
-            }
-        }
+        self.change_sample(next_wrapping);
     }
     pub fn handle_up_patterns(&mut self) {
-        if let Some(selected) = self.pattern_table_state.selected() {
-            let amount_patterns = self.patterns.len();
-            if selected > 0 {
-                self.pattern_table_state.select(Some(selected - 1));
-            } else {
-                self.pattern_table_state.select(Some(amount_patterns - 1));
-            }
+        if self.tree.is_some() {
+            self.change_tree(previous_wrapping);
+        } else {
+            self.change_pattern(previous_wrapping);
         }
     }
     pub fn handle_up_samples(&mut self) {
-        let current_amount_samples = self.current_amount_samples();
-        if let Some(selected) = self.sample_table_state.selected() {
-            if selected > 0 {
-                self.sample_table_state.select(Some(selected - 1));
-            } else {
-                self.sample_table_state
-                    .select(Some(current_amount_samples - 1));
+        self.change_sample(previous_wrapping);
+    }
+    pub fn handle_down_trace(&mut self) {
+        next_wrapping(&mut self.trace_table_state, self.trace_matches.len());
+    }
+    pub fn handle_up_trace(&mut self) {
+        previous_wrapping(&mut self.trace_table_state, self.trace_matches.len());
+    }
+
+    /// Collect the samples of all patterns sharing the trace ID of the
+    /// selected sample and show them in the Trace tab
+    pub fn show_trace(&mut self) {
+        let fields = &self.config.trace.fields;
+        let id = match self
+            .current_sample_rawlog()
+            .and_then(|rawlog| trace::trace_id(rawlog, fields))
+        {
+            Some(id) => id,
+            None => {
+                self.status = Some(format!("No trace ID in fields {}", fields.join(", ")));
+                return;
             }
+        };
+        self.trace_matches = trace::find(&self.patterns, fields, &id);
+        let current = TraceMatch {
+            pattern: self.current_pattern_index().unwrap_or(0),
+            sample: self.current_sample_index().unwrap_or(0),
+        };
+        let selected = self
+            .trace_matches
+            .iter()
+            .position(|m| *m == current)
+            .unwrap_or(0);
+        self.trace_table_state = TableState::default();
+        select_clamped(
+            &mut self.trace_table_state,
+            self.trace_matches.len(),
+            selected,
+        );
+        self.trace_id = Some(id);
+        self.select_tab(MenuItem::Trace);
+    }
+
+    /// The trace sample selected in the Trace tab
+    pub fn current_trace_match(&self) -> Option<TraceMatch> {
+        let selected = self.trace_table_state.selected()?;
+        self.trace_matches.get(selected).copied()
+    }
+
+    /// Select the pattern and sample of the selected trace sample and show it
+    /// in the Detail tab
+    pub fn open_trace_match(&mut self) {
+        if let Some(m) = self.current_trace_match() {
+            if !self.select_pattern(m.pattern) {
+                self.status = Some("The pattern is hidden by the filter".to_string());
+                return;
+            }
+            let sample_row = match self.sample_rows.iter().position(|&i| i == m.sample) {
+                Some(row) => row,
+                None => {
+                    self.status = Some("The sample is hidden by the filter".to_string());
+                    return;
+                }
+            };
+            self.change_sample(|state, len| select_clamped(state, len, sample_row));
+            self.select_tab(MenuItem::Details);
+        }
+    }
+
+    /// The selected pattern, `None` when the report is empty
+    pub fn current_pattern(&self) -> Option<&Pattern> {
+        self.current_pattern_index()
+            .map(|index| &self.patterns[index])
+    }
+
+    /// Index into `patterns` of the selected pattern
+    pub fn current_pattern_index(&self) -> Option<usize> {
+        self.pattern_table_state
+            .selected()
+            .and_then(|row| self.pattern_rows.get(row).copied())
+    }
+
+    /// Apply `change` to the pattern selection, remembering the view of the
+    /// pattern left and restoring the view of the pattern entered
+    fn change_pattern(&mut self, change: impl FnOnce(&mut TableState, usize)) {
+        let previous = self.current_pattern_index();
+        change(&mut self.pattern_table_state, self.pattern_rows.len());
+        let current = self.current_pattern_index();
+        if previous == current && previous.is_some() {
+            return;
+        }
+
+        if let Some(previous) = previous {
+            let view = PatternView {
+                selected_sample: self.current_sample_index(),
+                scroll: self.scroll,
+            };
+            self.pattern_views.insert(previous, view);
+        }
+        let view = current
+            .and_then(|current| self.pattern_views.get(&current).copied())
+            .unwrap_or_default();
+        self.sample_rows = self.visible_samples();
+        self.sample_table_state = TableState::default();
+        let row = view
+            .selected_sample
+            .and_then(|selected| self.sample_rows.iter().position(|&i| i == selected))
+            .unwrap_or(0);
+        select_clamped(&mut self.sample_table_state, self.sample_rows.len(), row);
+        self.refresh_detail();
+        self.scroll = view.scroll;
+    }
+
+    /// Apply `change` to the sample selection and show the new sample in Detail
+    fn change_sample(&mut self, change: impl FnOnce(&mut TableState, usize)) {
+        let previous = self.sample_table_state.selected();
+        let amount_samples = self.current_amount_samples();
+        change(&mut self.sample_table_state, amount_samples);
+        if self.sample_table_state.selected() != previous {
+            self.refresh_detail();
+        }
+    }
+
+    /// Render the selected sample into `current_rawlog` and scroll to its top
+    fn refresh_detail(&mut self) {
+        let rawlog = match self.current_sample_rawlog() {
+            Some(rawlog) => match serde_json::from_str::<serde_json::Value>(rawlog) {
+                Ok(json) => serde_json::to_string_pretty(&json).unwrap(),
+                Err(_) => rawlog.to_string(),
+            },
+            None => String::new(),
+        };
+        self.current_rawlog = self.redact(&rawlog).into_owned();
+        self.scroll = 0;
+    }
+
+    /// `rawlog` as it may be shown, redacted unless revealed
+    pub fn redact<'s>(&self, rawlog: &'s str) -> Cow<'s, str> {
+        if self.reveal {
+            Cow::Borrowed(rawlog)
+        } else {
+            self.redactor.redact(rawlog)
         }
     }
 
-    pub fn current_pattern(&self) -> &Pattern {
-        &self.patterns[self.pattern_table_state.selected().unwrap()]
+    /// Show or hide the personal data and secrets of samples, when the
+    /// config allows it
+    pub fn toggle_reveal(&mut self) {
+        if !self.config.redact.allow_reveal {
+            self.status = Some("Revealing samples is not allowed by the config".to_string());
+            return;
+        }
+        self.reveal = !self.reveal;
+        let scroll = self.scroll;
+        self.refresh_detail();
+        self.scroll = scroll;
+        self.status = Some(
+            if self.reveal {
+                "Samples are revealed"
+            } else {
+                "Samples are redacted"
+            }
+            .to_string(),
+        );
     }
 
     pub fn scroll_up(&mut self) {
@@ -173,12 +1305,340 @@ impl<'a> App<'a> {
     }
 
     pub fn scroll_down(&mut self) {
-        self.scroll += 1;
+        if self.scroll < self.detail_max_scroll {
+            self.scroll += 1;
+        }
+    }
+
+    /// Move the selection of the current tab one page down
+    pub fn page_down(&mut self) {
+        match self.current_menu_item() {
+            MenuItem::Pattern if self.tree.is_some() => {
+                let page = self.pattern_page_size as isize;
+                self.change_tree(|state, len| step(state, len, page));
+            }
+            MenuItem::Pattern => {
+                let page = self.pattern_page_size as isize;
+                self.change_pattern(|state, len| step(state, len, page));
+            }
+            MenuItem::Samples => {
+                let page = self.sample_page_size as isize;
+                self.change_sample(|state, len| step(state, len, page));
+            }
+            MenuItem::Details => {
+                self.scroll = self
+                    .scroll
+                    .saturating_add(self.detail_page_size)
+                    .min(self.detail_max_scroll);
+            }
+            MenuItem::Trace => {
+                let len = self.trace_matches.len();
+                step(
+                    &mut self.trace_table_state,
+                    len,
+                    self.trace_page_size as isize,
+                );
+            }
+        }
     }
 
-    pub fn current_sample_rawlog(&self) -> &str {
-        &self.patterns[self.pattern_table_state.selected().unwrap()].samples
-            [self.sample_table_state.selected().unwrap()]
-        .rawlog
+    /// Move the selection of the current tab one page up
+    pub fn page_up(&mut self) {
+        match self.current_menu_item() {
+            MenuItem::Pattern if self.tree.is_some() => {
+                let page = self.pattern_page_size as isize;
+                self.change_tree(|state, len| step(state, len, -page));
+            }
+            MenuItem::Pattern => {
+                let page = self.pattern_page_size as isize;
+                self.change_pattern(|state, len| step(state, len, -page));
+            }
+            MenuItem::Samples => {
+                let page = self.sample_page_size as isize;
+                self.change_sample(|state, len| step(state, len, -page));
+            }
+            MenuItem::Details => {
+                self.scroll = self.scroll.saturating_sub(self.detail_page_size);
+            }
+            MenuItem::Trace => {
+                let len = self.trace_matches.len();
+                step(
+                    &mut self.trace_table_state,
+                    len,
+                    -(self.trace_page_size as isize),
+                );
+            }
+        }
+    }
+
+    /// Select the first row of the current tab
+    pub fn go_top(&mut self) {
+        self.jump_to(1);
+    }
+
+    /// Select the last row of the current tab
+    pub fn go_bottom(&mut self) {
+        self.jump_to(usize::MAX);
+    }
+
+    /// Select the 1-based `row` of the current tab, clamped to the rows available
+    pub fn jump_to(&mut self, row: usize) {
+        let index = row.saturating_sub(1);
+        match self.current_menu_item() {
+            MenuItem::Pattern if self.tree.is_some() => {
+                self.change_tree(|state, len| select_clamped(state, len, index));
+            }
+            MenuItem::Pattern => {
+                self.change_pattern(|state, len| select_clamped(state, len, index));
+            }
+            MenuItem::Samples => {
+                self.change_sample(|state, len| select_clamped(state, len, index));
+            }
+            MenuItem::Details => {
+                self.scroll = index.min(self.detail_max_scroll as usize) as u16;
+            }
+            MenuItem::Trace => {
+                let len = self.trace_matches.len();
+                select_clamped(&mut self.trace_table_state, len, index);
+            }
+        }
+    }
+
+    /// Start typing a `:` command
+    pub fn start_command(&mut self) {
+        self.command = Some(String::new());
+    }
+
+    /// Run the typed `:` command and leave command mode
+    ///
+    /// - `:42` jumps to row 42
+    /// - `:w <path>` saves the content of the current tab to `path`
+    /// - `:filter <query>` shows only what matches `query`, `:filter` alone
+    ///   shows everything again
+    pub fn run_command(&mut self) {
+        let command = match self.command.take() {
+            Some(command) => command,
+            None => return,
+        };
+        let command = command.trim();
+        if let Ok(row) = command.parse::<usize>() {
+            self.jump_to(row);
+        } else if let Some(path) = command.strip_prefix("w ") {
+            self.save_current(path.trim());
+        } else if command == "tree" || command.starts_with("tree ") {
+            let fields = command[4..].split_whitespace().map(str::to_string);
+            self.show_tree(fields.collect());
+        } else if command == "view" || command.starts_with("view ") {
+            self.run_view(command[4..].trim());
+        } else if let Some(sort) = command.strip_prefix("sort ") {
+            match SortKey::parse(sort.trim()) {
+                Some(sort) => self.set_sort(sort),
+                None => {
+                    self.status = Some("Sort by count, pattern or cost".to_string());
+                }
+            }
+        } else if command == "columns" || command.starts_with("columns ") {
+            match Column::parse_list(&command[7..]) {
+                Some(columns) => self.columns = columns,
+                None => {
+                    self.status = Some("Columns are count, percent, cost and app".to_string());
+                }
+            }
+        } else if command == "filter" {
+            self.run_filter("");
+        } else if let Some(query) = command.strip_prefix("filter ") {
+            self.run_filter(query.trim());
+        } else if !command.is_empty() {
+            self.status = Some(format!("Unknown command: {command}"));
+        }
+    }
+
+    /// Parse and apply the filter `query`, an empty one removes the filter
+    pub fn run_filter(&mut self, query: &str) {
+        if query.is_empty() {
+            self.set_filter(None);
+            self.status = Some("Filter removed".to_string());
+            return;
+        }
+        match Query::parse(query) {
+            Ok(filter) => {
+                self.set_filter(Some(filter));
+                self.status = Some(format!(
+                    "{} of {} patterns match",
+                    self.matching_patterns,
+                    self.patterns.len()
+                ));
+            }
+            Err(e) => self.status = Some(format!("Invalid filter: {e}")),
+        }
     }
+
+    /// Text a copy or save acts on: the pattern text in the Pattern tab, the
+    /// sample rawlog in the Sample and Trace tabs and the pretty printed log
+    /// in Detail, redacted like they are shown
+    pub fn current_content(&self) -> Option<String> {
+        match self.current_menu_item() {
+            MenuItem::Pattern => self.current_pattern().map(|p| p.patterns.clone()),
+            MenuItem::Samples => self
+                .current_sample_rawlog()
+                .map(|rawlog| self.redact(rawlog).into_owned()),
+            MenuItem::Details => {
+                Some(self.current_rawlog.clone()).filter(|rawlog| !rawlog.is_empty())
+            }
+            MenuItem::Trace => self.current_trace_match().map(|m| {
+                let sample = &self.patterns[m.pattern].samples()[m.sample];
+                self.redact(&sample.rawlog).into_owned()
+            }),
+        }
+    }
+
+    /// Write the content of the current tab to the file at `path`
+    pub fn save_current(&mut self, path: &str) {
+        let content = match self.current_content() {
+            Some(content) => content,
+            None => {
+                self.status = Some("Nothing to save".to_string());
+                return;
+            }
+        };
+        self.status = Some(match fs::write(path, &content) {
+            Ok(()) => format!("Saved {} bytes to {path}", content.len()),
+            Err(e) => format!("Save to {path} failed: {e}"),
+        });
+    }
+
+    /// Source location that logged the selected sample, see [`source::locate`]
+    pub fn current_source_location(&self) -> Option<SourceLocation> {
+        source::locate(self.current_sample_rawlog()?, &self.config.source)
+    }
+
+    /// Rawlog of the selected sample, `None` when the pattern has no samples
+    pub fn current_sample_rawlog(&self) -> Option<&str> {
+        let selected = self.current_sample_index()?;
+        self.current_pattern()?
+            .samples()
+            .get(selected)
+            .map(|sample| sample.rawlog.as_str())
+    }
+
+    /// Apply a key press. What needs the terminal is left to the caller.
+    pub fn handle_key(&mut self, key: KeyEvent) -> KeyOutcome {
+        self.status = None;
+        let scope = self.key_scope();
+        match (self.keymap.action(scope, key.code), key.code) {
+            (Some(action), _) => self.apply(action),
+            (None, KeyCode::Char(c)) if scope == Scope::Command => {
+                if let Some(command) = &mut self.command {
+                    command.push(c);
+                }
+                KeyOutcome::Handled
+            }
+            (None, _) => KeyOutcome::Handled,
+        }
+    }
+
+    /// Where keys go: the view menu or the command line when open, else
+    /// the current tab
+    pub fn key_scope(&self) -> Scope {
+        if self.view_menu.is_some() {
+            Scope::ViewMenu
+        } else if self.command.is_some() {
+            Scope::Command
+        } else {
+            Scope::Tab(self.current_menu_item())
+        }
+    }
+
+    /// Do `action`. What needs the terminal is left to the caller.
+    pub fn apply(&mut self, action: Action) -> KeyOutcome {
+        match action {
+            Action::Quit => return KeyOutcome::Quit,
+            Action::CancelLoading => self.cancel_loading(),
+            Action::NextTab => self.on_right(),
+            Action::PreviousTab => self.on_left(),
+            Action::NextPattern => self.handle_down_patterns(),
+            Action::PreviousPattern => self.handle_up_patterns(),
+            Action::NextSample => self.handle_down_samples(),
+            Action::PreviousSample => self.handle_up_samples(),
+            Action::ScrollDown => self.scroll_down(),
+            Action::ScrollUp => self.scroll_up(),
+            Action::NextTraceMatch => self.handle_down_trace(),
+            Action::PreviousTraceMatch => self.handle_up_trace(),
+            Action::PageDown => self.page_down(),
+            Action::PageUp => self.page_up(),
+            Action::Top => self.go_top(),
+            Action::Bottom => self.go_bottom(),
+            Action::StartCommand => self.start_command(),
+            Action::Copy => match self.current_content() {
+                Some(content) => return KeyOutcome::Copy(content),
+                None => self.status = Some("Nothing to copy".to_string()),
+            },
+            Action::ShowTrace => self.show_trace(),
+            Action::OpenTraceMatch => self.open_trace_match(),
+            Action::OpenSource => match self.current_source_location() {
+                Some(location) => return KeyOutcome::OpenEditor(location),
+                None => {
+                    self.status = Some(format!(
+                        "No source location in field `{}`",
+                        self.config.source.field
+                    ))
+                }
+            },
+            Action::ToggleReveal => self.toggle_reveal(),
+            Action::ToggleGroup => self.toggle_group(),
+            Action::ToggleTree => self.toggle_tree(),
+            Action::ToggleChart => self.toggle_chart(),
+            Action::MarkOrMerge => self.mark_or_merge(),
+            Action::SplitPattern => self.split_pattern(),
+            Action::ToggleBookmark => self.toggle_bookmark(),
+            Action::NextBookmark => self.next_bookmark(),
+            Action::OpenViewMenu => self.open_view_menu(),
+            Action::RunCommand => self.run_command(),
+            Action::CancelCommand => self.command = None,
+            Action::DeleteCommandChar => {
+                if let Some(command) = &mut self.command {
+                    command.pop();
+                }
+            }
+            Action::NextView => self.move_view_menu(1),
+            Action::PreviousView => self.move_view_menu(-1),
+            Action::ApplyView => self.apply_menu_view(),
+            Action::CloseViewMenu => self.view_menu = None,
+        }
+        KeyOutcome::Handled
+    }
+}
+
+/// Move the selection of `state` by `delta` rows, clamped to `[0, len)`
+fn step(state: &mut TableState, len: usize, delta: isize) {
+    let selected = state.selected().unwrap_or(0) as isize;
+    let index = (selected + delta).max(0) as usize;
+    select_clamped(state, len, index);
+}
+
+/// Select `index` in `state`, clamped to `[0, len)`, or nothing when empty
+fn select_clamped(state: &mut TableState, len: usize, index: usize) {
+    if len > 0 {
+        state.select(Some(index.min(len - 1)));
+    } else {
+        state.select(None);
+    }
+}
+
+/// Select the row after the current one, wrapping to the first
+fn next_wrapping(state: &mut TableState, len: usize) {
+    let next = match state.selected() {
+        Some(selected) if selected + 1 < len => selected + 1,
+        _ => 0,
+    };
+    select_clamped(state, len, next);
+}
+
+/// Select the row before the current one, wrapping to the last
+fn previous_wrapping(state: &mut TableState, len: usize) {
+    let previous = match state.selected() {
+        Some(selected) if selected > 0 => selected - 1,
+        _ => len.saturating_sub(1),
+    };
+    select_clamped(state, len, previous);
 }
@@ -1,4 +1,5 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::net::SocketAddr;
 
 /// Simple program to view log patterns
 #[derive(Parser, Debug)]
@@ -12,9 +13,9 @@ pub struct Args {
     #[clap(long)]
     pub namespace: Option<String>,
 
-    /// Name of app
+    /// Name of app, repeat to merge several apps, `*` for all apps of the namespace
     #[clap(long)]
-    pub name: Option<String>,
+    pub name: Vec<String>,
 
     /// Year of report
     #[clap(short, long)]
@@ -31,4 +32,91 @@ pub struct Args {
     /// aws region name
     #[clap(long)]
     pub region: Option<String>,
+
+    /// Config file, defaults to config.toml in the config dir
+    #[clap(short, long)]
+    pub config: Option<String>,
+
+    /// Only use reports cached from earlier downloads
+    #[clap(long)]
+    pub offline: bool,
+
+    /// Only show patterns and samples matching this query, e.g.
+    /// `count > 100 and sample.level = "error"`
+    #[clap(long)]
+    pub filter: Option<String>,
+
+    /// Open with the filter, sort, columns and tab of this saved view
+    #[clap(long)]
+    pub view: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage reports cached from s3
+    #[clap(subcommand)]
+    Cache(CacheCommand),
+    /// Check reports for problems, exits non-zero when any is found
+    Validate {
+        /// Report files to check
+        #[clap(required = true)]
+        paths: Vec<String>,
+        /// Require every rawlog to be a JSON object
+        #[clap(long)]
+        json: bool,
+    },
+    /// Export pattern counts as OpenMetrics text. Without a report file the
+    /// report is loaded from s3.
+    Metrics {
+        /// Report file to export
+        report: Option<String>,
+        /// File to write the metrics to, stdout when neither this nor
+        /// --listen is set
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Serve the metrics on this address, e.g. 127.0.0.1:9898
+        #[clap(short, long)]
+        listen: Option<SocketAddr>,
+        /// JSON field of rawlogs holding the log level
+        #[clap(long, default_value = "level")]
+        level_field: String,
+    },
+    /// Serve reports over a local HTTP API with a web viewer. Without
+    /// report files the s3 report and the one of the previous month are
+    /// served.
+    Serve {
+        /// Report files to serve
+        reports: Vec<String>,
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+    /// Check a report against rules, exits non-zero when any rule fails.
+    /// Without a report file the report is loaded from s3.
+    Check {
+        /// Rules file
+        #[clap(short, long, default_value = "checks.toml")]
+        rules: String,
+        /// Report file to check
+        report: Option<String>,
+        /// Report to compare against, the previous month of the s3 report
+        /// when not set
+        #[clap(short, long)]
+        baseline: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// List cached reports
+    List,
+    /// Delete cached reports
+    Prune {
+        /// Only delete reports fetched more than this many days ago
+        #[clap(long)]
+        older_than: Option<i64>,
+    },
 }
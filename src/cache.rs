@@ -0,0 +1,314 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const APP_DIR: &str = "log-pattern-viewer";
+const META_SUFFIX: &str = ".meta.json";
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Reports downloaded from s3, stored under the cache dir by bucket and key
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// Cached copy of an s3 object
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub bucket: String,
+    pub key: String,
+    pub path: PathBuf,
+    pub meta: CacheMeta,
+}
+
+/// What we know about the object a cache entry was downloaded from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheMeta {
+    pub etag: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+impl Cache {
+    /// Cache in the user cache dir, e.g. `~/.cache/log-pattern-viewer`
+    pub fn open() -> Option<Cache> {
+        dirs::cache_dir().map(|dir| Cache::at(dir.join(APP_DIR)))
+    }
+
+    pub fn at(dir: PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    fn path(&self, bucket: &str, key: &str) -> PathBuf {
+        self.dir.join(bucket).join(key)
+    }
+
+    /// The cached copy of `bucket`/`key`, if there is a complete one
+    pub fn get(&self, bucket: &str, key: &str) -> Option<CacheEntry> {
+        let path = self.path(bucket, key);
+        let meta = fs::read_to_string(with_suffix(&path, META_SUFFIX)).ok()?;
+        let meta = serde_json::from_str(&meta).ok()?;
+        path.is_file().then(|| CacheEntry {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            path,
+            meta,
+        })
+    }
+
+    /// Start writing a new copy of `bucket`/`key`. The entry only replaces
+    /// the current one once [`CacheWriter::finish`] is called.
+    pub fn writer(&self, bucket: &str, key: &str, etag: Option<String>) -> io::Result<CacheWriter> {
+        let path = self.path(bucket, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = with_suffix(&path, PARTIAL_SUFFIX);
+        Ok(CacheWriter {
+            file: BufWriter::new(File::create(&partial)?),
+            partial,
+            path,
+            etag,
+            size: 0,
+        })
+    }
+
+    /// All complete entries, ordered by bucket and key
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(entries);
+        }
+        for bucket in fs::read_dir(&self.dir)? {
+            let bucket = bucket?;
+            if !bucket.file_type()?.is_dir() {
+                continue;
+            }
+            let mut files = Vec::new();
+            collect_files(&bucket.path(), &mut files)?;
+            for file in files {
+                let key = match file.strip_prefix(bucket.path()) {
+                    Ok(key) => key.to_string_lossy().replace('\\', "/"),
+                    Err(_) => continue,
+                };
+                let bucket = bucket.file_name().to_string_lossy().to_string();
+                if let Some(entry) = self.get(&bucket, &key) {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries.sort_by(|a, b| (&a.bucket, &a.key).cmp(&(&b.bucket, &b.key)));
+        Ok(entries)
+    }
+
+    /// Delete a cached copy
+    pub fn remove(&self, entry: &CacheEntry) -> io::Result<()> {
+        fs::remove_file(with_suffix(&entry.path, META_SUFFIX))?;
+        fs::remove_file(&entry.path)
+    }
+}
+
+/// Writes a download into the cache as it is read. Dropping it before
+/// [`CacheWriter::finish`], e.g. when the download is cancelled, deletes
+/// the partial copy.
+pub struct CacheWriter {
+    file: BufWriter<File>,
+    partial: PathBuf,
+    path: PathBuf,
+    etag: Option<String>,
+    size: u64,
+}
+
+impl CacheWriter {
+    /// Make the written copy the cache entry
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.partial, &self.path)?;
+        let meta = CacheMeta {
+            etag: self.etag.take(),
+            fetched_at: Utc::now(),
+            size: self.size,
+        };
+        fs::write(
+            with_suffix(&self.path, META_SUFFIX),
+            serde_json::to_vec(&meta)?,
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        // gone already when finished
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+impl Write for CacheWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Copies everything read from `inner` into `writer`, if there is one
+pub struct TeeReader<R, W> {
+    inner: R,
+    writer: Option<W>,
+}
+
+impl<R, W> TeeReader<R, W> {
+    pub fn new(inner: R, writer: Option<W>) -> Self {
+        TeeReader { inner, writer }
+    }
+
+    pub fn into_writer(self) -> Option<W> {
+        self.writer
+    }
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(&buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Report files under `dir`, skipping our own meta and partial files
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            let name = path.to_string_lossy();
+            if !name.ends_with(META_SUFFIX) && !name.ends_with(PARTIAL_SUFFIX) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// Empty cache in a temp dir of its own
+    fn cache(name: &str) -> Cache {
+        let dir = env::temp_dir().join(format!("log-pattern-viewer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache::at(dir)
+    }
+
+    fn files(cache: &Cache) -> Vec<String> {
+        let mut files = Vec::new();
+        let mut dirs = vec![cache.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let name = path.strip_prefix(&cache.dir).unwrap();
+                    files.push(name.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn finished_writes_become_entries() {
+        let cache = cache("finish");
+        let mut writer = cache
+            .writer("bucket", "2022-02/app.json", Some("\"abc\"".to_string()))
+            .unwrap();
+        writer.write_all(b"[]").unwrap();
+        assert_eq!(files(&cache), ["bucket/2022-02/app.json.partial"]);
+        assert!(cache.get("bucket", "2022-02/app.json").is_none());
+
+        writer.finish().unwrap();
+        assert_eq!(
+            files(&cache),
+            [
+                "bucket/2022-02/app.json",
+                "bucket/2022-02/app.json.meta.json"
+            ]
+        );
+        let entry = cache.get("bucket", "2022-02/app.json").unwrap();
+        assert_eq!(entry.meta.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(entry.meta.size, 2);
+        assert_eq!(fs::read_to_string(&entry.path).unwrap(), "[]");
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        cache.remove(&entry).unwrap();
+        assert!(cache.get("bucket", "2022-02/app.json").is_none());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn entries_without_a_readable_meta_are_skipped() {
+        let cache = cache("meta");
+        for key in ["missing", "corrupt"] {
+            let mut writer = cache.writer("bucket", key, None).unwrap();
+            writer.write_all(b"[]").unwrap();
+            writer.finish().unwrap();
+        }
+        fs::remove_file(cache.dir.join("bucket/missing.meta.json")).unwrap();
+        fs::write(cache.dir.join("bucket/corrupt.meta.json"), "{\"etag\":").unwrap();
+        assert!(cache.get("bucket", "missing").is_none());
+        assert!(cache.get("bucket", "corrupt").is_none());
+        assert!(cache.entries().unwrap().is_empty());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_downloads_leave_no_entry() {
+        let cache = cache("cancel");
+        let mut tee = TeeReader::new(
+            &b"[{\"patterns\":"[..],
+            Some(cache.writer("bucket", "app.json", None).unwrap()),
+        );
+        let mut buf = [0; 4];
+        tee.read_exact(&mut buf).unwrap();
+        drop(tee);
+        assert!(cache.get("bucket", "app.json").is_none());
+        assert!(files(&cache).is_empty());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn tee_copies_what_is_read() {
+        let mut tee = TeeReader::new(&b"report"[..], Some(Vec::new()));
+        let mut read = String::new();
+        tee.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "report");
+        assert_eq!(tee.into_writer().unwrap(), b"report");
+
+        let mut tee = TeeReader::new(&b"report"[..], None::<Vec<u8>>);
+        let mut read = Vec::new();
+        tee.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"report");
+        assert!(tee.into_writer().is_none());
+    }
+}
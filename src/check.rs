@@ -0,0 +1,343 @@
+use crate::{error::Error, pattern::Pattern};
+use regex::Regex;
+use serde::Deserialize;
+use std::{collections::HashSet, fmt, fs};
+
+/// New patterns listed in the outcome of a failed rule
+const LISTED_PATTERNS: usize = 5;
+
+/// Rules a report is checked against, usually kept in the repo of a service
+/// as `checks.toml`
+#[derive(Deserialize, Debug)]
+pub struct Rules {
+    /// JSON field of rawlogs holding the log level
+    #[serde(default = "default_level_field")]
+    pub level_field: String,
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+fn default_level_field() -> String {
+    "level".to_string()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Rule {
+    /// Shown in the summary instead of the description of the rule
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub kind: RuleKind,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum RuleKind {
+    /// No pattern missing from the baseline report, only counting patterns
+    /// with samples at `level` when it is set
+    NoNewPatterns { level: Option<String> },
+    /// Patterns matching the `pattern` regex make up at most `max` percent
+    MaxPercent { pattern: String, max: f32 },
+    /// The top pattern, of those matching `pattern` if set, has a count of
+    /// at most `max`
+    MaxCount { pattern: Option<String>, max: usize },
+}
+
+/// Result of checking one rule
+pub struct Outcome {
+    pub rule: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        write!(f, "{} {}: {}", status, self.rule, self.detail)
+    }
+}
+
+impl Rules {
+    pub fn load(path: &str) -> Result<Rules, Error> {
+        let content = fs::read_to_string(path)?;
+        let rules: Rules = toml::from_str(&content).map_err(Error::ParseRulesError)?;
+        for rule in &rules.rules {
+            match &rule.kind {
+                RuleKind::MaxPercent { pattern, .. }
+                | RuleKind::MaxCount {
+                    pattern: Some(pattern),
+                    ..
+                } => {
+                    Regex::new(pattern)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Whether any rule compares against a baseline report
+    pub fn needs_baseline(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule.kind, RuleKind::NoNewPatterns { .. }))
+    }
+
+    /// Check every rule against `report`. Rules that need a baseline fail
+    /// without one.
+    pub fn check(&self, report: &[Pattern], baseline: Option<&[Pattern]>) -> Vec<Outcome> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let (passed, detail) = self.check_rule(&rule.kind, report, baseline);
+                Outcome {
+                    rule: rule.name.clone().unwrap_or_else(|| rule.kind.to_string()),
+                    passed,
+                    detail,
+                }
+            })
+            .collect()
+    }
+
+    fn check_rule(
+        &self,
+        kind: &RuleKind,
+        report: &[Pattern],
+        baseline: Option<&[Pattern]>,
+    ) -> (bool, String) {
+        match kind {
+            RuleKind::NoNewPatterns { level } => {
+                let baseline = match baseline {
+                    Some(baseline) => baseline,
+                    None => return (false, "no baseline report".to_string()),
+                };
+                let known: HashSet<&str> = baseline.iter().map(|p| p.patterns.as_str()).collect();
+                let new: Vec<&Pattern> = report
+                    .iter()
+                    .filter(|p| !known.contains(p.patterns.as_str()))
+                    .filter(|p| {
+                        level
+                            .as_deref()
+                            .is_none_or(|level| self.has_level(p, level))
+                    })
+                    .collect();
+                if new.is_empty() {
+                    return (true, "no new patterns".to_string());
+                }
+                let mut detail = format!("{} new patterns", new.len());
+                for pattern in new.iter().take(LISTED_PATTERNS) {
+                    detail.push_str(&format!("\n    {} {}", pattern.count, pattern.patterns));
+                }
+                if new.len() > LISTED_PATTERNS {
+                    detail.push_str("\n    ...");
+                }
+                (false, detail)
+            }
+            RuleKind::MaxPercent { pattern, max } => {
+                let regex = Regex::new(pattern).expect("rules are validated on load");
+                let total: usize = report.iter().map(|p| p.count).sum();
+                let matching: usize = report
+                    .iter()
+                    .filter(|p| regex.is_match(&p.patterns))
+                    .map(|p| p.count)
+                    .sum();
+                let percent = if total == 0 {
+                    0.0
+                } else {
+                    matching as f32 / total as f32 * 100.0
+                };
+                (
+                    percent <= *max,
+                    format!("{:.2}% of {} logs, max {:.2}%", percent, total, max),
+                )
+            }
+            RuleKind::MaxCount { pattern, max } => {
+                let regex = pattern
+                    .as_deref()
+                    .map(|pattern| Regex::new(pattern).expect("rules are validated on load"));
+                let top = report
+                    .iter()
+                    .filter(|p| {
+                        regex
+                            .as_ref()
+                            .is_none_or(|regex| regex.is_match(&p.patterns))
+                    })
+                    .max_by_key(|p| p.count);
+                match top {
+                    Some(top) => (
+                        top.count <= *max,
+                        format!("top count {} of `{}`, max {}", top.count, top.patterns, max),
+                    ),
+                    None => (true, "no matching pattern".to_string()),
+                }
+            }
+        }
+    }
+
+    /// Whether any sample of `pattern` was logged at `level`
+    fn has_level(&self, pattern: &Pattern, level: &str) -> bool {
+        pattern.samples().iter().any(|sample| {
+            sample
+                .level(&self.level_field)
+                .is_some_and(|l| l.eq_ignore_ascii_case(level))
+        })
+    }
+}
+
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleKind::NoNewPatterns { level: Some(level) } => {
+                write!(f, "no new {} patterns", level)
+            }
+            RuleKind::NoNewPatterns { level: None } => write!(f, "no new patterns"),
+            RuleKind::MaxPercent { pattern, max } => {
+                write!(f, "/{}/ at most {}%", pattern, max)
+            }
+            RuleKind::MaxCount {
+                pattern: Some(pattern),
+                max,
+            } => write!(f, "top /{}/ count at most {}", pattern, max),
+            RuleKind::MaxCount { pattern: None, max } => {
+                write!(f, "top count at most {}", max)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::pattern;
+    use std::{env, process};
+
+    fn rules(toml: &str) -> Rules {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn logged_at(text: &str, count: usize, level: &str) -> Pattern {
+        let rawlog = format!(r#"{{"level":"{}"}}"#, level);
+        let samples = serde_json::json!([
+            { "predict": 0, "date": "2022-02-12T00:00:00Z", "rawlog": rawlog },
+        ]);
+        pattern(text, count, &samples.to_string())
+    }
+
+    fn report() -> Vec<Pattern> {
+        vec![
+            logged_at("queue is full", 75, "error"),
+            logged_at("request took Number ms", 25, "info"),
+        ]
+    }
+
+    fn passed(rules: &Rules, baseline: Option<&[Pattern]>) -> Vec<bool> {
+        rules
+            .check(&report(), baseline)
+            .iter()
+            .map(|outcome| outcome.passed)
+            .collect()
+    }
+
+    #[test]
+    fn new_patterns_need_a_baseline() {
+        let rules = rules("[[rule]]\nkind = \"no-new-patterns\"");
+        assert!(rules.needs_baseline());
+        let outcomes = rules.check(&report(), None);
+        assert!(!outcomes[0].passed);
+        assert_eq!(outcomes[0].detail, "no baseline report");
+    }
+
+    #[test]
+    fn new_patterns_are_listed() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            kind = "no-new-patterns"
+
+            [[rule]]
+            kind = "no-new-patterns"
+            level = "ERROR"
+            "#,
+        );
+        let baseline = vec![logged_at("queue is full", 1, "error")];
+        let outcomes = rules.check(&report(), Some(&baseline));
+        assert_eq!(
+            outcomes[0].to_string(),
+            "FAIL no new patterns: 1 new patterns\n    25 request took Number ms"
+        );
+        assert_eq!(
+            outcomes[1].to_string(),
+            "PASS no new ERROR patterns: no new patterns"
+        );
+        assert_eq!(passed(&rules, Some(&report())), [true, true]);
+    }
+
+    #[test]
+    fn max_percent_allows_its_limit() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            kind = "max-percent"
+            pattern = "took"
+            max = 25.0
+
+            [[rule]]
+            kind = "max-percent"
+            pattern = "took"
+            max = 24.9
+            "#,
+        );
+        assert_eq!(passed(&rules, None), [true, false]);
+    }
+
+    #[test]
+    fn max_count_allows_its_limit() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            kind = "max-count"
+            max = 75
+
+            [[rule]]
+            name = "errors"
+            kind = "max-count"
+            max = 74
+
+            [[rule]]
+            kind = "max-count"
+            pattern = "^request"
+            max = 25
+
+            [[rule]]
+            kind = "max-count"
+            pattern = "nothing matches"
+            max = 0
+            "#,
+        );
+        assert_eq!(passed(&rules, None), [true, false, true, true]);
+        let outcomes = rules.check(&report(), None);
+        assert_eq!(
+            outcomes[1].to_string(),
+            "FAIL errors: top count 75 of `queue is full`, max 74"
+        );
+    }
+
+    #[test]
+    fn invalid_regexes_fail_to_load() {
+        let path =
+            env::temp_dir().join(format!("log-pattern-viewer-checks-{}.toml", process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            "[[rule]]\nkind = \"max-percent\"\npattern = \"(\"\nmax = 1.0",
+        )
+        .unwrap();
+        assert!(matches!(Rules::load(path), Err(Error::RegexError(_))));
+        fs::write(
+            path,
+            "[[rule]]\nkind = \"max-percent\"\npattern = \"ok\"\nmax = 1.0",
+        )
+        .unwrap();
+        assert_eq!(Rules::load(path).unwrap().rules.len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,18 @@
+use std::{
+    env,
+    io::{self, Write},
+};
+
+/// Copy `text` to the system clipboard with an OSC 52 escape sequence.
+///
+/// The terminal emulator behind `out` sets its clipboard, which also works
+/// over SSH. Inside tmux the sequence is wrapped so tmux passes it through.
+pub fn copy<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    let encoded = base64::encode(text);
+    if env::var_os("TMUX").is_some() {
+        write!(out, "\x1bPtmux;\x1b\x1b]52;c;{encoded}\x07\x1b\\")?;
+    } else {
+        write!(out, "\x1b]52;c;{encoded}\x07")?;
+    }
+    out.flush()
+}
@@ -0,0 +1,209 @@
+use crate::{error::Error, redact::Redactor};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+const APP_DIR: &str = "log-pattern-viewer";
+const CONFIG_FILE: &str = "config.toml";
+
+/// User settings, read from `config.toml` in the config dir
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub source: SourceConfig,
+    pub trace: TraceConfig,
+    pub redact: RedactConfig,
+    pub group: GroupConfig,
+    pub tree: TreeConfig,
+    pub chart: ChartConfig,
+    pub cost: CostConfig,
+}
+
+/// Where to find the code that logged a sample
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SourceConfig {
+    /// JSON field of the rawlog holding the source location
+    pub field: String,
+    /// Regex extracting the `file` and `line` named groups from the field
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    /// Local checkout relative file paths are resolved against
+    pub root: Option<PathBuf>,
+    /// Checkouts per app, keyed by the `app` field of the rawlog
+    pub roots: HashMap<String, PathBuf>,
+    /// Editor command, `{file}` and `{line}` are replaced.
+    /// Defaults to `$VISUAL` or `$EDITOR` with `+{line} {file}`
+    pub editor: Option<String>,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig {
+            field: "caller".to_string(),
+            pattern: Regex::new(r"(?P<file>[^\s:]+):(?P<line>\d+)").unwrap(),
+            root: None,
+            roots: HashMap::new(),
+            editor: None,
+        }
+    }
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// How samples of the same request are correlated
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TraceConfig {
+    /// JSON fields of the rawlog holding the trace ID, alternative names
+    /// when apps log it differently. Span IDs must not be listed, they
+    /// don't identify a trace.
+    pub fields: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            fields: vec!["xxttxx".to_string()],
+        }
+    }
+}
+
+/// What is redacted from samples before they are shown or exported
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RedactConfig {
+    pub enabled: bool,
+    /// Built-in detectors to run, see [`crate::redact::DETECTORS`]
+    pub detectors: Vec<String>,
+    /// JSON fields of the rawlog whose values are always redacted
+    pub fields: Vec<String>,
+    pub rules: Vec<RedactRule>,
+    /// Whether redacted samples may be revealed in the viewer
+    pub allow_reveal: bool,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        RedactConfig {
+            enabled: true,
+            detectors: crate::redact::DETECTORS
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            fields: Vec::new(),
+            rules: Vec::new(),
+            allow_reveal: false,
+        }
+    }
+}
+
+/// Text matching `pattern` is replaced by `[REDACTED:<name>]`
+#[derive(Deserialize, Debug, Clone)]
+pub struct RedactRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// When patterns are similar enough to be grouped in the Pattern table
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// Whether similar patterns are grouped automatically
+    pub enabled: bool,
+    /// Most tokens that may differ between patterns of a group
+    pub max_distance: usize,
+    /// Least share of tokens patterns of a group have in common
+    pub min_similarity: f32,
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        GroupConfig {
+            enabled: true,
+            max_distance: 2,
+            min_similarity: 0.8,
+        }
+    }
+}
+
+/// Fields the tree view of patterns branches on, outermost first
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TreeConfig {
+    pub fields: Vec<String>,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        TreeConfig {
+            fields: vec!["caller".to_string(), "level".to_string()],
+        }
+    }
+}
+
+/// Volume chart of the Pattern tab
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChartConfig {
+    /// Patterns with the highest counts shown as bars
+    pub top: usize,
+}
+
+impl Default for ChartConfig {
+    fn default() -> Self {
+        ChartConfig { top: 10 }
+    }
+}
+
+/// Prices estimating what the logs of a pattern cost a month
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CostConfig {
+    /// Price of ingesting a GB of logs
+    pub ingest_per_gb: f64,
+    /// Price of storing a GB of logs for their retention
+    pub storage_per_gb: f64,
+    /// Size of a log line, the average size of the samples of each pattern
+    /// when unset
+    pub bytes_per_line: Option<f64>,
+    /// Prefix of amounts
+    pub currency: String,
+}
+
+impl Default for CostConfig {
+    fn default() -> Self {
+        CostConfig {
+            ingest_per_gb: 0.0,
+            storage_per_gb: 0.0,
+            bytes_per_line: None,
+            currency: "$".to_string(),
+        }
+    }
+}
+
+/// Directory holding our config files, e.g. `~/.config/log-pattern-viewer`
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR))
+}
+
+impl Config {
+    /// Load config from `path`, or from the config dir when no path is given.
+    /// A missing file in the config dir is not an error.
+    pub fn load(path: Option<&str>) -> Result<Config, Error> {
+        let content = match path {
+            Some(path) => fs::read_to_string(path)?,
+            None => match config_dir().map(|dir| fs::read_to_string(dir.join(CONFIG_FILE))) {
+                Some(Ok(content)) => content,
+                Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => return Ok(Config::default()),
+            },
+        };
+        let config: Config = toml::from_str(&content)?;
+        Redactor::new(&config.redact)?;
+        Ok(config)
+    }
+}
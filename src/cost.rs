@@ -0,0 +1,95 @@
+use crate::{config::CostConfig, pattern::Pattern};
+
+const BYTES_PER_GB: f64 = 1e9;
+
+/// Estimated monthly cost of the logs of patterns. Counts are taken as the
+/// lines of a month, which reports cover.
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    price_per_gb: f64,
+    bytes_per_line: Option<f64>,
+    currency: String,
+}
+
+impl CostModel {
+    /// Model of `config`, `None` when no price is set
+    pub fn new(config: &CostConfig) -> Option<CostModel> {
+        let price_per_gb = config.ingest_per_gb + config.storage_per_gb;
+        if price_per_gb <= 0.0 {
+            return None;
+        }
+        Some(CostModel {
+            price_per_gb,
+            bytes_per_line: config.bytes_per_line,
+            currency: config.currency.clone(),
+        })
+    }
+
+    /// Bytes of a line of `pattern`: the configured size, or the average
+    /// JSON size of its samples, or the size of the pattern text when it has
+    /// none. Samples are measured undecoded, their dates count in.
+    pub fn bytes_per_line(&self, pattern: &Pattern) -> f64 {
+        self.bytes_per_line
+            .or_else(|| pattern.sample_size())
+            .unwrap_or(pattern.patterns.len() as f64)
+    }
+
+    pub fn cost(&self, pattern: &Pattern) -> f64 {
+        self.cost_of(pattern.count, self.bytes_per_line(pattern))
+    }
+
+    /// Cost of `count` lines of `bytes_per_line` bytes
+    pub fn cost_of(&self, count: usize, bytes_per_line: f64) -> f64 {
+        count as f64 * bytes_per_line / BYTES_PER_GB * self.price_per_gb
+    }
+
+    /// `amount` with the currency, e.g. `$12.34`
+    pub fn format(&self, amount: f64) -> String {
+        format!("{}{:.2}", self.currency, amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::pattern;
+
+    fn model(bytes_per_line: Option<f64>) -> CostModel {
+        CostModel::new(&CostConfig {
+            ingest_per_gb: 0.5,
+            storage_per_gb: 0.5,
+            bytes_per_line,
+            ..CostConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn no_price_no_model() {
+        assert!(CostModel::new(&CostConfig::default()).is_none());
+    }
+
+    #[test]
+    fn lines_are_the_average_sample_size() {
+        let samples = concat!(
+            r#"[{"predict":0,"date":"2022-02-12T00:00:00Z","rawlog":"12345678"},"#,
+            r#"{"predict":0,"date":"2022-02-12T00:00:00Z","rawlog":"1234"}]"#,
+        );
+        let pattern = pattern("queue is full", 1_000_000_000, samples);
+        assert_eq!(model(None).bytes_per_line(&pattern), 62.5);
+        assert_eq!(model(None).cost(&pattern), 62.5);
+        assert_eq!(model(Some(100.0)).cost(&pattern), 100.0);
+        assert!(pattern.try_samples().is_ok());
+    }
+
+    #[test]
+    fn patterns_without_samples_fall_back_to_the_pattern_size() {
+        assert_eq!(
+            model(None).bytes_per_line(&pattern("queue is full", 10, "[]")),
+            13.0
+        );
+        let pattern = pattern("queue is full", 10, "[{\"predict\":");
+        assert_eq!(model(None).bytes_per_line(&pattern), 13.0);
+        assert_eq!(model(None).format(1.0 / 3.0), "$0.33");
+    }
+}
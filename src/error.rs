@@ -7,4 +7,24 @@ pub enum Error {
     ReadDBError(#[from] io::Error),
     #[error("error parsing the DB file: {0}")]
     ParseDBError(#[from] serde_json::Error),
+    #[error("error parsing the config file: {0}")]
+    ParseConfigError(#[from] toml::de::Error),
+    #[error("error parsing the rules file: {0}")]
+    ParseRulesError(toml::de::Error),
+    #[error("invalid regex: {0}")]
+    RegexError(#[from] regex::Error),
+    #[error("error reading the groups file: {0}")]
+    ReadGroupsError(io::Error),
+    #[error("error parsing the groups file: {0}")]
+    ParseGroupsError(toml::de::Error),
+    #[error("error writing the groups file: {0}")]
+    WriteGroupsError(io::Error),
+    #[error("error reading the views file: {0}")]
+    ReadViewsError(io::Error),
+    #[error("error parsing the views file: {0}")]
+    ParseViewsError(toml::de::Error),
+    #[error("error writing the views file: {0}")]
+    WriteViewsError(io::Error),
+    #[error("unknown redaction detector `{0}`")]
+    UnknownDetector(String),
 }
@@ -0,0 +1,302 @@
+use crate::{
+    config::{config_dir, GroupConfig},
+    error::Error,
+    metrics::pattern_id,
+    pattern::Pattern,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const GROUPS_FILE: &str = "groups.toml";
+
+/// Groups merged or split by hand, by pattern id so they apply to every
+/// report
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Overrides {
+    /// Pairs of patterns always in the same group
+    pub merged: Vec<(String, String)>,
+    /// Patterns never grouped by similarity
+    pub split: Vec<String>,
+}
+
+impl Overrides {
+    /// `groups.toml` in the config dir
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(GROUPS_FILE))
+    }
+
+    /// Load overrides from `path`, a missing file has none
+    pub fn load(path: &Path) -> Result<Overrides, Error> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(Error::ParseGroupsError),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Overrides::default()),
+            Err(e) => Err(Error::ReadGroupsError(e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::WriteGroupsError)?;
+        }
+        let content = toml::to_string(self).expect("overrides serialize to toml");
+        fs::write(path, content).map_err(Error::WriteGroupsError)
+    }
+}
+
+/// Groups of similar patterns. Patterns are similar when few of their
+/// tokens differ; a group holds every pattern reachable through similar
+/// ones, or through pairs merged by hand.
+pub struct Groups {
+    config: GroupConfig,
+    overrides: Overrides,
+    /// Where overrides are saved, `None` to keep them in memory
+    path: Option<PathBuf>,
+    tokens: Vec<Vec<String>>,
+    ids: Vec<String>,
+    /// Indexes of the patterns similar to each pattern
+    similar: Vec<Vec<usize>>,
+    /// Indexes of the patterns by their number of tokens, as only patterns
+    /// of about the same length can be similar
+    by_len: HashMap<usize, Vec<usize>>,
+    /// Group of each pattern, the lowest index of its members
+    group: Vec<usize>,
+    /// Number of members of each group, by group
+    sizes: HashMap<usize, usize>,
+}
+
+impl Groups {
+    pub fn new(config: GroupConfig, overrides: Overrides, path: Option<PathBuf>) -> Groups {
+        Groups {
+            config,
+            overrides,
+            path,
+            tokens: Vec::new(),
+            ids: Vec::new(),
+            similar: Vec::new(),
+            by_len: HashMap::new(),
+            group: Vec::new(),
+            sizes: HashMap::new(),
+        }
+    }
+
+    /// Group the patterns added to `patterns` since the last update
+    pub fn update(&mut self, patterns: &[Pattern]) {
+        if patterns.len() == self.ids.len() {
+            return;
+        }
+        for pattern in &patterns[self.ids.len()..] {
+            let index = self.ids.len();
+            let tokens: Vec<String> = pattern
+                .patterns
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+            let mut similar = Vec::new();
+            if self.config.enabled {
+                let len = tokens.len();
+                let lens =
+                    len.saturating_sub(self.config.max_distance)..=len + self.config.max_distance;
+                for other_len in lens {
+                    for &other in self.by_len.get(&other_len).into_iter().flatten() {
+                        if is_similar(&tokens, &self.tokens[other], &self.config) {
+                            similar.push(other);
+                            self.similar[other].push(index);
+                        }
+                    }
+                }
+            }
+            self.by_len.entry(tokens.len()).or_default().push(index);
+            self.tokens.push(tokens);
+            self.ids.push(pattern_id(&pattern.patterns));
+            self.similar.push(similar);
+        }
+        self.rebuild();
+    }
+
+    /// Group of `patterns[index]`, the same for every member
+    pub fn group(&self, index: usize) -> usize {
+        self.group[index]
+    }
+
+    /// Number of patterns in the group of `patterns[index]`
+    pub fn size(&self, index: usize) -> usize {
+        self.sizes[&self.group[index]]
+    }
+
+    /// Put the groups of `patterns[a]` and `patterns[b]` together
+    pub fn merge(&mut self, a: usize, b: usize) -> Result<(), Error> {
+        let pair = (self.ids[a].clone(), self.ids[b].clone());
+        self.overrides
+            .split
+            .retain(|id| *id != pair.0 && *id != pair.1);
+        self.overrides.merged.push(pair);
+        self.rebuild();
+        self.save()
+    }
+
+    /// Take `patterns[index]` out of its group
+    pub fn split(&mut self, index: usize) -> Result<(), Error> {
+        let id = self.ids[index].clone();
+        self.overrides.merged.retain(|(a, b)| *a != id && *b != id);
+        if !self.overrides.split.contains(&id) {
+            self.overrides.split.push(id);
+        }
+        self.rebuild();
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        match &self.path {
+            Some(path) => self.overrides.save(path),
+            None => Ok(()),
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let mut parent: Vec<usize> = (0..self.ids.len()).collect();
+        let split: HashSet<&str> = self.overrides.split.iter().map(String::as_str).collect();
+        for (a, similar) in self.similar.iter().enumerate() {
+            if split.contains(self.ids[a].as_str()) {
+                continue;
+            }
+            for &b in similar {
+                if !split.contains(self.ids[b].as_str()) {
+                    union(&mut parent, a, b);
+                }
+            }
+        }
+        let index: HashMap<&str, usize> = self
+            .ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.as_str(), i))
+            .collect();
+        for (a, b) in &self.overrides.merged {
+            if let (Some(&a), Some(&b)) = (index.get(a.as_str()), index.get(b.as_str())) {
+                union(&mut parent, a, b);
+            }
+        }
+        self.group = (0..parent.len()).map(|i| find(&mut parent, i)).collect();
+        self.sizes.clear();
+        for &group in &self.group {
+            *self.sizes.entry(group).or_default() += 1;
+        }
+    }
+}
+
+/// Root of the set of `index`, halving the path to it on the way
+fn find(parent: &mut [usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        parent[index] = parent[parent[index]];
+        index = parent[index];
+    }
+    index
+}
+
+/// Join the sets of `a` and `b` under the lower root, so a group keeps its
+/// root while patterns are added
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    parent[high] = low;
+}
+
+fn is_similar(a: &[String], b: &[String], config: &GroupConfig) -> bool {
+    let longest = a.len().max(b.len());
+    let allowed = config
+        .max_distance
+        .min(((1.0 - config.min_similarity) * longest as f32 + 1e-4).floor() as usize);
+    if longest == 0 || a.len().abs_diff(b.len()) > allowed {
+        return false;
+    }
+    allowed > 0 && distance(a, b) <= allowed
+}
+
+/// Edit distance between token lists: tokens inserted, removed or replaced
+fn distance(a: &[String], b: &[String]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_token) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_token) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_token != b_token);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::pattern;
+
+    fn patterns(texts: &[&str]) -> Vec<Pattern> {
+        texts.iter().map(|text| pattern(text, 1, "[]")).collect()
+    }
+
+    fn groups(texts: &[&str]) -> Groups {
+        let mut groups = Groups::new(GroupConfig::default(), Overrides::default(), None);
+        groups.update(&patterns(texts));
+        groups
+    }
+
+    #[test]
+    fn groups_patterns_differing_in_few_tokens() {
+        let groups = groups(&[
+            "user Number logged in from web console",
+            "disk full on volume data",
+            "user Number logged in from mobile console",
+            "user Number logged out from mobile console",
+        ]);
+        assert_eq!(groups.group(2), 0);
+        assert_eq!(groups.group(3), 0);
+        assert_eq!(groups.group(1), 1);
+        assert_eq!((groups.size(0), groups.size(1)), (3, 1));
+    }
+
+    #[test]
+    fn lengths_far_apart_are_never_compared_similar() {
+        let groups = groups(&[
+            "a b c d e f g h i j",
+            "a b c d e f g h i j k",
+            "a b c d e f g h i j k l m n",
+        ]);
+        assert_eq!(groups.group(1), 0);
+        assert_eq!(groups.group(2), 2);
+    }
+
+    #[test]
+    fn patterns_added_later_join_groups() {
+        let all = patterns(&[
+            "cache miss for key Number in region east",
+            "queue is full",
+            "cache miss for key Number in region west",
+        ]);
+        let mut groups = Groups::new(GroupConfig::default(), Overrides::default(), None);
+        groups.update(&all[..2]);
+        assert_eq!(groups.size(0), 1);
+        groups.update(&all);
+        assert_eq!((groups.group(2), groups.size(0)), (0, 2));
+    }
+
+    #[test]
+    fn merge_and_split_override_similarity() {
+        let mut groups = groups(&[
+            "payment accepted for order Number today",
+            "payment refused for order Number today",
+            "queue is full",
+        ]);
+        groups.merge(0, 2).unwrap();
+        assert_eq!((groups.group(2), groups.size(0)), (0, 3));
+        groups.split(1).unwrap();
+        assert_eq!((groups.group(1), groups.size(1)), (1, 1));
+        assert_eq!(groups.size(0), 2);
+    }
+}
@@ -1,6 +1,22 @@
 pub mod app;
 pub mod args;
+pub mod cache;
+pub mod check;
+pub mod clipboard;
+pub mod config;
+pub mod cost;
 pub mod error;
+pub mod group;
+pub mod loader;
+pub mod metrics;
 pub mod pattern;
+pub mod query;
+pub mod redact;
 pub mod s3;
+pub mod serve;
+pub mod source;
+pub mod trace;
+pub mod tree;
 pub mod ui;
+pub mod validate;
+pub mod view;
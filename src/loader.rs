@@ -0,0 +1,575 @@
+use crate::{
+    app::Event,
+    cache::{Cache, CacheWriter, TeeReader},
+    error::Error,
+    pattern::{Pattern, ReportMeta, SCHEMA_VERSION},
+    s3::{self, Fetched},
+};
+use rusoto_s3::S3Client;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::{
+    fmt,
+    fs::File,
+    future::Future,
+    io::{self, BufReader, Read},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+/// How often the loader hands parsed patterns to the ui
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where a report is loaded from
+pub enum Source {
+    File(String),
+    /// Reports of one or more apps of a namespace, merged into one
+    S3 {
+        profile: Option<String>,
+        region: Option<String>,
+        namespace: String,
+        /// Apps to load, [`ALL_APPS`] loads every app of the namespace
+        apps: Vec<String>,
+        year: i32,
+        month: i32,
+        /// Only use cached copies
+        offline: bool,
+    },
+}
+
+/// App name selecting every app of a namespace
+pub const ALL_APPS: &str = "*";
+
+/// What the loader is doing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadStage {
+    /// Setting up credentials and requesting the report
+    Connecting,
+    /// Downloading and parsing a remote report
+    Downloading,
+    /// Parsing a local report
+    Parsing,
+}
+
+/// Stage of the loader, bytes of the report read so far, and the total
+/// when known. Several reports are loaded one after another, `part` is
+/// the 1-based number of the current one.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadProgress {
+    pub stage: LoadStage,
+    pub read: u64,
+    pub total: Option<u64>,
+    pub part: usize,
+    pub parts: usize,
+}
+
+impl LoadProgress {
+    pub fn new(stage: LoadStage) -> Self {
+        LoadProgress {
+            stage,
+            read: 0,
+            total: None,
+            part: 1,
+            parts: 1,
+        }
+    }
+
+    /// Share of the report read, between 0 and 1 when the total is known
+    pub fn ratio(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.read as f64 / total as f64).min(1.0))
+    }
+}
+
+/// Handle on a loader running in the background
+pub struct LoadHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LoadHandle {
+    /// Stop loading, patterns already sent are kept
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Load the report from `source` on a background thread.
+///
+/// Progress is sent as [`Event::Progress`] and patterns in batches as
+/// [`Event::Patterns`] while they are parsed. [`Event::Loaded`] ends the
+/// load.
+pub fn spawn<I: Send + 'static>(source: Source, tx: Sender<Event<I>>) -> LoadHandle {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handle = LoadHandle {
+        cancelled: cancelled.clone(),
+    };
+    thread::spawn(move || {
+        let error = match load(source, &tx, &cancelled) {
+            Ok(()) => None,
+            Err(_) if cancelled.load(Ordering::SeqCst) => Some("cancelled".to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        let _ = tx.send(Event::Loaded(error));
+    });
+    handle
+}
+
+/// Load every pattern of `source` with the metadata of its reports,
+/// blocking until it's done
+pub fn load_patterns(source: Source) -> anyhow::Result<(Vec<Pattern>, Vec<ReportMeta>)> {
+    let (tx, rx) = mpsc::channel::<Event<()>>();
+    spawn(source, tx);
+    let mut patterns = Vec::new();
+    let mut reports = Vec::new();
+    for event in rx {
+        match event {
+            Event::Patterns(batch) => patterns.extend(batch),
+            Event::Report(meta) => reports.push(meta),
+            Event::Loaded(Some(error)) => return Err(anyhow::anyhow!(error)),
+            Event::Loaded(None) => break,
+            _ => {}
+        }
+    }
+    Ok((patterns, reports))
+}
+
+/// A report opened for reading
+struct Opened {
+    reader: Box<dyn Read>,
+    stage: LoadStage,
+    total: Option<u64>,
+    /// Copy of the download being written to the cache
+    cache: Option<CacheWriter>,
+}
+
+fn load<I>(
+    source: Source,
+    tx: &Sender<Event<I>>,
+    cancelled: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    let (profile, region, namespace, apps, year, month, offline) = match source {
+        Source::File(path) => {
+            let opened = open_file(Path::new(&path))?;
+            return stream_report(opened, None, (1, 1), tx, cancelled);
+        }
+        Source::S3 {
+            profile,
+            region,
+            namespace,
+            apps,
+            year,
+            month,
+            offline,
+        } => (profile, region, namespace, apps, year, month, offline),
+    };
+
+    let _ = tx.send(Event::Progress(LoadProgress::new(LoadStage::Connecting)));
+    // the runtime must outlive the readers to keep driving s3 downloads
+    let rt = Runtime::new()?;
+    let cache = Cache::open();
+    let client = if offline {
+        None
+    } else {
+        Some(s3::client(profile.as_deref(), region.as_deref())?)
+    };
+
+    let all_apps = apps.iter().any(|app| app == ALL_APPS);
+    let apps = match (&client, all_apps) {
+        (_, false) => apps,
+        (Some(client), true) => rt.block_on(cancellable(
+            s3::list_report_apps(client, &namespace, year, month),
+            cancelled,
+        ))?,
+        (None, true) => cached_apps(cache.as_ref(), &namespace, year, month)?,
+    };
+    if apps.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no reports for {namespace} in {year}-{month:0>2}"
+        ));
+    }
+
+    // patterns are only tagged with their app when several are merged
+    let tag = all_apps || apps.len() > 1;
+    for (i, app) in apps.iter().enumerate() {
+        let key = s3::report_file_key(&namespace, app, year, month);
+        let opened = match &client {
+            Some(client) => open_s3(&rt, client, cache.as_ref(), &key, tx, cancelled)?,
+            None => match cache.as_ref().and_then(|cache| cache.get(s3::BUCKET, &key)) {
+                Some(entry) => open_file(&entry.path)?,
+                None => return Err(anyhow::anyhow!("s3://{}/{key} is not cached", s3::BUCKET)),
+            },
+        };
+        let app = if tag { Some(app.as_str()) } else { None };
+        stream_report(opened, app, (i + 1, apps.len()), tx, cancelled)?;
+    }
+    Ok(())
+}
+
+/// Parse an opened report, sending its patterns tagged with `app`
+fn stream_report<I>(
+    opened: Opened,
+    app: Option<&str>,
+    (part, parts): (usize, usize),
+    tx: &Sender<Event<I>>,
+    cancelled: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    let stage = opened.stage;
+    let total = opened.total;
+
+    let read = Arc::new(AtomicU64::new(0));
+    let progress = |read: &AtomicU64| LoadProgress {
+        stage,
+        read: read.load(Ordering::Relaxed),
+        total,
+        part,
+        parts,
+    };
+    let _ = tx.send(Event::Progress(progress(&read)));
+    let mut tee = TeeReader::new(opened.reader, opened.cache);
+    let reader = CountingReader {
+        inner: &mut tee,
+        read: read.clone(),
+        cancelled: cancelled.clone(),
+    };
+    let mut batch = Vec::new();
+    let mut last_sent = Instant::now();
+    let meta = stream_patterns(reader, |mut pattern| {
+        pattern.app = app.map(str::to_string);
+        batch.push(pattern);
+        if last_sent.elapsed() >= BATCH_INTERVAL {
+            let _ = tx.send(Event::Progress(progress(&read)));
+            let _ = tx.send(Event::Patterns(std::mem::take(&mut batch)));
+            last_sent = Instant::now();
+        }
+    })?;
+    // caching is best effort, the report is loaded either way
+    if let Some(cache) = tee.into_writer() {
+        let _ = cache.finish();
+    }
+
+    let _ = tx.send(Event::Progress(progress(&read)));
+    let _ = tx.send(Event::Patterns(batch));
+    if let Some(meta) = meta {
+        let _ = tx.send(Event::Report(meta));
+    }
+    Ok(())
+}
+
+/// Open the report at `key`, from the cache when it's still current
+fn open_s3<I>(
+    rt: &Runtime,
+    client: &S3Client,
+    cache: Option<&Cache>,
+    key: &str,
+    tx: &Sender<Event<I>>,
+    cancelled: &Arc<AtomicBool>,
+) -> anyhow::Result<Opened> {
+    let _ = tx.send(Event::Progress(LoadProgress::new(LoadStage::Connecting)));
+    let cached = cache.and_then(|cache| cache.get(s3::BUCKET, key));
+    let etag = cached.as_ref().and_then(|entry| entry.meta.etag.as_deref());
+    let fetched = rt.block_on(cancellable(
+        s3::open_object(client, s3::BUCKET, key, etag),
+        cancelled,
+    ))?;
+
+    match (fetched, cached) {
+        (Fetched::NotModified, Some(entry)) => open_file(&entry.path),
+        (Fetched::NotModified, None) => Err(anyhow::anyhow!(
+            "s3 answered not modified without a cached copy"
+        )),
+        (Fetched::Body { body, len, etag }, _) => Ok(Opened {
+            reader: Box::new(body.into_blocking_read()),
+            stage: LoadStage::Downloading,
+            total: len,
+            cache: cache.and_then(|cache| cache.writer(s3::BUCKET, key, etag).ok()),
+        }),
+    }
+}
+
+fn open_file(path: &Path) -> anyhow::Result<Opened> {
+    let file = File::open(path)?;
+    let total = file.metadata().ok().map(|m| m.len());
+    Ok(Opened {
+        reader: Box::new(file),
+        stage: LoadStage::Parsing,
+        total,
+        cache: None,
+    })
+}
+
+/// Apps of namespace with a cached report for year and month
+fn cached_apps(
+    cache: Option<&Cache>,
+    namespace: &str,
+    year: i32,
+    month: i32,
+) -> anyhow::Result<Vec<String>> {
+    let mut apps = Vec::new();
+    if let Some(cache) = cache {
+        for entry in cache.entries()? {
+            if entry.bucket != s3::BUCKET {
+                continue;
+            }
+            if let Some(app) = s3::report_file_app(&entry.key, namespace) {
+                if entry.key == s3::report_file_key(namespace, app, year, month) {
+                    apps.push(app.to_string());
+                }
+            }
+        }
+    }
+    Ok(apps)
+}
+
+/// Run `future` until it's done or the load is cancelled
+async fn cancellable<T>(
+    future: impl Future<Output = anyhow::Result<T>>,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<T> {
+    tokio::select! {
+        result = future => result,
+        _ = wait_cancelled(cancelled) => Err(anyhow::anyhow!("cancelled")),
+    }
+}
+
+async fn wait_cancelled(cancelled: &AtomicBool) {
+    while !cancelled.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Read a whole report file, patterns in file order
+pub fn read_report_file(path: &str) -> Result<Vec<Pattern>, Error> {
+    let mut patterns = Vec::new();
+    stream_patterns(File::open(path)?, |pattern| patterns.push(pattern))?;
+    Ok(patterns)
+}
+
+/// Parse a report from `reader`, passing each pattern to `on_pattern` as
+/// soon as it is parsed. Reports are either a bare array of patterns or an
+/// envelope holding them, whose metadata is returned.
+pub fn stream_patterns<R: Read>(
+    reader: R,
+    on_pattern: impl FnMut(Pattern),
+) -> Result<Option<ReportMeta>, Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let meta = Report(on_pattern).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(meta)
+}
+
+/// Visits a report, either an array of patterns or an envelope object
+struct Report<F>(F);
+
+impl<'de, F: FnMut(Pattern)> DeserializeSeed<'de> for Report<F> {
+    type Value = Option<ReportMeta>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(Pattern)> Visitor<'de> for Report<F> {
+    type Value = Option<ReportMeta>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of patterns or a report object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, seq: A) -> Result<Self::Value, A::Error> {
+        PatternSeq(&mut self.0).visit_seq(seq)?;
+        Ok(None)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut meta = ReportMeta::default();
+        let mut version = None;
+        let mut patterns = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "schema_version" => {
+                    let v: u32 = map.next_value()?;
+                    if v > SCHEMA_VERSION {
+                        return Err(de::Error::custom(format!(
+                            "unsupported schema_version {}, expected at most {}",
+                            v, SCHEMA_VERSION
+                        )));
+                    }
+                    version = Some(v);
+                }
+                "namespace" => meta.namespace = map.next_value()?,
+                "app" => meta.app = map.next_value()?,
+                "period" => meta.period = map.next_value()?,
+                "generator" => meta.generator = map.next_value()?,
+                "total_lines" => meta.total_lines = map.next_value()?,
+                "patterns" => {
+                    map.next_value_seed(PatternSeq(&mut self.0))?;
+                    patterns = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        meta.schema_version = version.ok_or_else(|| de::Error::missing_field("schema_version"))?;
+        if !patterns {
+            return Err(de::Error::missing_field("patterns"));
+        }
+        Ok(Some(meta))
+    }
+}
+
+/// Visits a JSON array of patterns one element at a time
+struct PatternSeq<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(Pattern)> DeserializeSeed<'de> for PatternSeq<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(Pattern)> Visitor<'de> for PatternSeq<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of patterns")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(pattern) = seq.next_element()? {
+            (self.0)(pattern);
+        }
+        Ok(())
+    }
+}
+
+/// Counts the bytes read through it into a shared counter, and fails once
+/// the load is cancelled
+struct CountingReader<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(io::Error::other("cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERN: &str = r#"{"patterns":"queue is full","count":3,"percent":null,"samples":[]}"#;
+
+    fn load(report: &str) -> Result<(Vec<Pattern>, Option<ReportMeta>), Error> {
+        let mut patterns = Vec::new();
+        let meta = stream_patterns(report.as_bytes(), |pattern| patterns.push(pattern))?;
+        Ok((patterns, meta))
+    }
+
+    /// Reads at most 16 bytes at a time, like a slow download
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(16);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn native_arrays_have_no_meta() {
+        let (patterns, meta) = load(&format!("[{},{}]", PATTERN, PATTERN)).unwrap();
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].patterns, "queue is full");
+        assert!(meta.is_none());
+    }
+
+    #[test]
+    fn envelopes_carry_meta_in_any_order() {
+        let report = format!(
+            r#"{{"patterns":[{}],"app":"numbers","extra":{{"ignored":[1]}},"schema_version":1,"total_lines":10}}"#,
+            PATTERN
+        );
+        let (patterns, meta) = load(&report).unwrap();
+        assert_eq!(patterns.len(), 1);
+        let meta = meta.unwrap();
+        assert_eq!(meta.schema_version, 1);
+        assert_eq!(meta.app.as_deref(), Some("numbers"));
+        assert_eq!(meta.total_lines, Some(10));
+    }
+
+    #[test]
+    fn envelopes_need_a_supported_version_and_patterns() {
+        let missing_version = format!(r#"{{"patterns":[{}]}}"#, PATTERN);
+        let error = load(&missing_version).err().unwrap().to_string();
+        assert!(
+            error.contains("missing field `schema_version`"),
+            "{}",
+            error
+        );
+
+        let error = load(r#"{"schema_version":1}"#).err().unwrap().to_string();
+        assert!(error.contains("missing field `patterns`"), "{}", error);
+
+        let error = load(r#"{"schema_version":2,"patterns":[]}"#)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("unsupported schema_version 2"), "{}", error);
+
+        assert!(load(r#""patterns""#).is_err());
+        assert!(load(&format!("[{}] trailing", PATTERN)).is_err());
+    }
+
+    #[test]
+    fn cancelling_stops_the_stream() {
+        let report = format!("[{}]", [PATTERN; 10].join(","));
+        let read = Arc::new(AtomicU64::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reader = CountingReader {
+            inner: Trickle(report.as_bytes()),
+            read: read.clone(),
+            cancelled: cancelled.clone(),
+        };
+        let mut loaded = 0;
+        let result = stream_patterns(reader, |_| {
+            loaded += 1;
+            cancelled.store(true, Ordering::SeqCst);
+        });
+        let error = result.err().unwrap().to_string();
+        assert!(error.contains("cancelled"), "{}", error);
+        assert_eq!(loaded, 1);
+        assert!(read.load(Ordering::Relaxed) < report.len() as u64);
+    }
+
+    #[test]
+    fn counting_reader_counts_every_byte() {
+        let report = format!("[{}]", PATTERN);
+        let read = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: Trickle(report.as_bytes()),
+            read: read.clone(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        stream_patterns(reader, |_| {}).unwrap();
+        assert_eq!(read.load(Ordering::Relaxed), report.len() as u64);
+    }
+}
@@ -1,101 +1,343 @@
+use chrono::Utc;
 use clap::Parser;
 use crossterm::{
-    event::{self, Event as CEvent, KeyCode},
-    terminal::enable_raw_mode,
+    event::{self, Event as CEvent},
+    terminal::{disable_raw_mode, enable_raw_mode},
 };
 use log_pattern_viewer::{
-    app::{App, Event, MenuItem},
-    args::Args,
-    error::Error,
-    pattern::Pattern,
-    s3::{read_report_file_from_key, report_file_key},
+    app::{App, Event, KeyOutcome},
+    args::{Args, CacheCommand, Command},
+    cache::Cache,
+    check::Rules,
+    clipboard,
+    config::Config,
+    cost::CostModel,
+    group::{Groups, Overrides},
+    loader::{self, LoadProgress, LoadStage, Source},
+    metrics,
+    query::Query,
+    redact::Redactor,
+    serve::{self, Report},
+    source::{editor_command, SourceLocation},
     ui::draw,
+    validate::validate,
+    view::Saved,
 };
-use rusoto_core::request::HttpClient;
-use rusoto_credential::ProfileProvider;
-use rusoto_s3::S3Client;
 use std::{
     fs, io,
+    net::SocketAddr,
+    path::Path,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
     sync::mpsc,
-    sync::mpsc::channel,
+    sync::Arc,
     thread,
     time::{Duration, Instant},
 };
-use tokio::runtime::Runtime;
 use tui::{backend::CrosstermBackend, Terminal};
 
-fn read_from_remote(args: &Args) -> anyhow::Result<Vec<Pattern>> {
-    let (tx, rx) = channel();
+fn remote_source(args: &Args) -> anyhow::Result<Source> {
+    match (&args.namespace, args.year, args.month) {
+        (Some(namespace), Some(year), Some(month)) if !args.name.is_empty() => Ok(Source::S3 {
+            profile: args.profile.clone(),
+            region: args.region.clone(),
+            namespace: namespace.clone(),
+            apps: args.name.clone(),
+            year,
+            month,
+            offline: args.offline,
+        }),
+        _ => Err(anyhow::anyhow!("namespace, app, year, month must be set")),
+    }
+}
 
-    let profile = if args.profile.is_some() {
-        println!("Using profile: {}", args.profile.as_ref().unwrap());
-        ProfileProvider::with_default_credentials(args.profile.as_ref().unwrap())?
-    } else {
-        ProfileProvider::new()?
+fn run_cache(command: &CacheCommand) -> anyhow::Result<()> {
+    let cache = Cache::open().ok_or_else(|| anyhow::anyhow!("no cache dir on this system"))?;
+    match command {
+        CacheCommand::List => {
+            for entry in cache.entries()? {
+                println!(
+                    "{:>10}  {}  s3://{}/{}",
+                    entry.meta.size,
+                    entry.meta.fetched_at.format("%Y-%m-%d %H:%M"),
+                    entry.bucket,
+                    entry.key
+                );
+            }
+        }
+        CacheCommand::Prune { older_than } => {
+            let now = Utc::now();
+            let mut pruned = 0;
+            for entry in cache.entries()? {
+                let expired = older_than
+                    .is_none_or(|days| now - entry.meta.fetched_at > chrono::Duration::days(days));
+                if expired {
+                    cache.remove(&entry)?;
+                    pruned += 1;
+                }
+            }
+            println!("Pruned {pruned} cached reports");
+        }
+    }
+    Ok(())
+}
+
+/// Print the problems found in each report, returns whether there were any
+fn run_validate(paths: &[String], json: bool) -> anyhow::Result<bool> {
+    let mut problems = 0;
+    for path in paths {
+        let text = fs::read_to_string(path)?;
+        for diagnostic in validate(&text, json) {
+            println!("{path}:{diagnostic}");
+            problems += 1;
+        }
+    }
+    if problems > 0 {
+        eprintln!("{problems} problems found");
+    }
+    Ok(problems > 0)
+}
+
+/// Check a report against the rules in `rules`, returns whether any failed
+fn run_check(
+    args: &Args,
+    rules: &str,
+    report: Option<&str>,
+    baseline: Option<&str>,
+) -> anyhow::Result<bool> {
+    let rules = Rules::load(rules)?;
+    let source = match report {
+        Some(path) => Source::File(path.to_string()),
+        None => remote_source(args)?,
     };
-    let region = args
-        .region
-        .as_ref()
-        .unwrap_or(&"cn-northwest-1".to_string())
-        .parse()?;
-    let s3 = S3Client::new_with(HttpClient::new()?, profile, region);
-
-    let rt = Runtime::new().unwrap();
-    let namespace = &args.namespace;
-    let app = &args.name;
-    let year = args.year;
-    let month = args.month;
-
-    if namespace.is_none() || app.is_none() || year.is_none() || month.is_none() {
-        return Err(anyhow::anyhow!("namespace, app, year, month must be set"));
-    }
-    let key = report_file_key(
-        namespace.as_ref().unwrap(),
-        app.as_ref().unwrap(),
-        year.unwrap(),
-        month.unwrap(),
-    );
-    rt.block_on(async {
-        // retrieve report file
-        match read_report_file_from_key(&s3, &key).await {
-            Ok(report) => {
-                tx.send(report).unwrap();
+    let baseline = match (baseline, report) {
+        (Some(path), _) => Some(Source::File(path.to_string())),
+        (None, _) if !rules.needs_baseline() => None,
+        (None, Some(_)) => anyhow::bail!("a baseline report is needed to check for new patterns"),
+        (None, None) => {
+            let mut source = remote_source(args)?;
+            if let Source::S3 { year, month, .. } = &mut source {
+                let (y, m) = previous_month(*year, *month);
+                *year = y;
+                *month = m;
             }
-            Err(_) => {
-                // send error to main thread
-                // TODO: handle s3 read error
+            Some(source)
+        }
+    };
+
+    let (patterns, _) = loader::load_patterns(source)?;
+    let baseline = match baseline {
+        Some(baseline) => Some(loader::load_patterns(baseline)?.0),
+        None => None,
+    };
+    let outcomes = rules.check(&patterns, baseline.as_deref());
+    for outcome in &outcomes {
+        println!("{outcome}");
+    }
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed).count();
+    if failed > 0 {
+        eprintln!("{failed} of {} rules failed", outcomes.len());
+    }
+    Ok(failed > 0)
+}
+
+/// Write the pattern counts of a report as OpenMetrics text to `output`,
+/// or stdout, or serve them on `listen`
+fn run_metrics(
+    args: &Args,
+    report: Option<&str>,
+    output: Option<&str>,
+    listen: Option<SocketAddr>,
+    level_field: &str,
+) -> anyhow::Result<()> {
+    let source = match report {
+        Some(path) => Source::File(path.to_string()),
+        None => remote_source(args)?,
+    };
+    let (patterns, reports) = loader::load_patterns(source)?;
+    let meta = reports.first();
+    let single_app = match args.name.as_slice() {
+        [name] if name != loader::ALL_APPS => Some(name.clone()),
+        _ => None,
+    };
+    let labels = metrics::Labels {
+        namespace: args
+            .namespace
+            .clone()
+            .or_else(|| meta.and_then(|meta| meta.namespace.clone()))
+            .unwrap_or_default(),
+        app: single_app
+            .or_else(|| meta.and_then(|meta| meta.app.clone()))
+            .unwrap_or_default(),
+        level_field: level_field.to_string(),
+    };
+    let config = Config::load(args.config.as_deref())?;
+    let cost = CostModel::new(&config.cost);
+    let text = metrics::render(&patterns, &labels, cost.as_ref());
+
+    if let Some(path) = output {
+        fs::write(path, &text)?;
+    }
+    match listen {
+        Some(addr) => {
+            eprintln!("Serving metrics on http://{addr}/metrics");
+            tokio::runtime::Runtime::new()?.block_on(metrics::serve(addr, text))?;
+        }
+        None if output.is_none() => print!("{text}"),
+        None => {}
+    }
+    Ok(())
+}
+
+/// Serve `reports`, or the s3 report after the one of the previous month
+fn run_serve(args: &Args, reports: &[String], listen: SocketAddr) -> anyhow::Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let redactor = Redactor::new(&config.redact)?;
+    let cost = CostModel::new(&config.cost);
+    let mut sources = Vec::new();
+    for path in reports {
+        let name = Path::new(path)
+            .file_stem()
+            .map_or_else(|| path.clone(), |stem| stem.to_string_lossy().into_owned());
+        sources.push((name, Source::File(path.clone())));
+    }
+    if sources.is_empty() {
+        for previous in [true, false] {
+            let mut source = remote_source(args)?;
+            if let Source::S3 { year, month, .. } = &mut source {
+                if previous {
+                    let (y, m) = previous_month(*year, *month);
+                    *year = y;
+                    *month = m;
+                }
+                sources.push((format!("{}-{:02}", year, month), source));
             }
         }
-    });
-    println!("Receiving report {key} ...");
-    let reports = rx.recv().expect("Bad report format");
-    let mut patterns = read_report_from_str(&reports).expect("can fetch report");
-    patterns.sort_by(|a, b| b.count.cmp(&a.count));
+    }
+
+    let mut loaded: Vec<Report> = Vec::new();
+    for (mut name, source) in sources {
+        if loaded.iter().any(|report| report.name == name) {
+            name = format!("{}-{}", name, loaded.len());
+        }
+        let (patterns, meta) = loader::load_patterns(source)?;
+        loaded.push(Report::new(name, patterns, meta.into_iter().next()));
+    }
+    eprintln!("Serving {} reports on http://{listen}/", loaded.len());
+    tokio::runtime::Runtime::new()?.block_on(serve::serve(listen, loaded, redactor, cost))?;
+    Ok(())
+}
 
-    Ok(patterns)
+fn previous_month(year: i32, month: i32) -> (i32, i32) {
+    if month <= 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Cache(command)) => {
+            run_cache(command)?;
+            return Ok(());
+        }
+        Some(Command::Validate { paths, json }) => {
+            if run_validate(paths, *json)? {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Metrics {
+            report,
+            output,
+            listen,
+            level_field,
+        }) => {
+            run_metrics(
+                &args,
+                report.as_deref(),
+                output.as_deref(),
+                *listen,
+                level_field,
+            )?;
+            return Ok(());
+        }
+        Some(Command::Serve { reports, listen }) => {
+            run_serve(&args, reports, *listen)?;
+            return Ok(());
+        }
+        Some(Command::Check {
+            rules,
+            report,
+            baseline,
+        }) => {
+            if run_check(&args, rules, report.as_deref(), baseline.as_deref())? {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
     let local_file = &args.from_local;
+    let config = Config::load(args.config.as_deref())?;
+    let filter = args.filter.as_deref().map(Query::parse).transpose()?;
+    let groups_path = Overrides::default_path();
+    let overrides = match &groups_path {
+        Some(path) => Overrides::load(path)?,
+        None => Overrides::default(),
+    };
 
-    enable_raw_mode().expect("can run in raw mode");
+    let saved_path = Saved::default_path();
+    let saved = match &saved_path {
+        Some(path) => Saved::load(path)?,
+        None => Saved::default(),
+    };
+    let view = match &args.view {
+        Some(name) => match saved.view(name) {
+            Some(view) => Some(view.clone()),
+            None => return Err(anyhow::anyhow!("no saved view named `{}`", name).into()),
+        },
+        None => None,
+    };
 
-    let patterns = if local_file.is_none() {
-        read_from_remote(&args)?
-    } else {
-        read_report_from_file(local_file.as_ref().unwrap())?
+    let source = match local_file {
+        Some(path) => Source::File(path.clone()),
+        None => remote_source(&args)?,
     };
 
+    enable_raw_mode().expect("can run in raw mode");
+
+    let stage = match source {
+        Source::File(_) => LoadStage::Parsing,
+        Source::S3 { .. } => LoadStage::Connecting,
+    };
     let title = "Log Pattern Viewer";
-    let mut app = App::new(title, patterns);
-    app.calculate_percent();
+    let groups = Groups::new(config.group.clone(), overrides, groups_path);
+    let mut app = App::new(title, Vec::new(), config);
+    app.groups = groups;
+    app.saved = saved;
+    app.saved_path = saved_path;
+    app.set_filter(filter);
+    if let Some(view) = &view {
+        app.apply_view(view);
+    }
+    app.loading = Some(LoadProgress::new(stage));
 
     let (tx, rx) = mpsc::channel();
+    app.loader = Some(loader::spawn(source, tx.clone()));
     let tick_rate = Duration::from_millis(200);
+    // set while an external program owns the terminal
+    let paused = Arc::new(AtomicBool::new(false));
+    let input_paused = paused.clone();
     thread::spawn(move || {
         let mut last_tick = Instant::now();
         loop {
+            if input_paused.load(Ordering::SeqCst) {
+                thread::sleep(tick_rate);
+                continue;
+            }
             let timeout = tick_rate
                 .checked_sub(last_tick.elapsed())
                 .unwrap_or_else(|| Duration::from_secs(0));
@@ -121,61 +363,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         terminal.draw(|f| draw(f, &mut app))?;
 
         match rx.recv()? {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('q') => {
+            Event::Input(key) => match app.handle_key(key) {
+                KeyOutcome::Handled => {}
+                KeyOutcome::Quit => {
                     terminal.show_cursor()?;
                     break;
                 }
-                KeyCode::Char('p') => app.active_menu_item = MenuItem::Samples,
-                KeyCode::Char('a') => {}
-                KeyCode::Char('d') => {
-                    match app.current_menu_item() {
-                        MenuItem::Pattern => {}
-                        MenuItem::Samples => {
-                            // display json in third tab
-                            let rawlog = app.current_sample_rawlog();
-                            match serde_json::from_str::<serde_json::Value>(rawlog) {
-                                Ok(json) => {
-                                    let log = serde_json::to_string_pretty(&json).unwrap();
-                                    app.current_rawlog = log;
-                                }
-                                Err(_) => {
-                                    app.current_rawlog = rawlog.to_string();
-                                }
-                            }
-                            app.on_right();
-                        }
-                        MenuItem::Details => {
-                            app.scroll_down();
-                        }
+                KeyOutcome::OpenEditor(location) => {
+                    paused.store(true, Ordering::SeqCst);
+                    // let the input thread finish its current poll
+                    thread::sleep(tick_rate);
+                    let result = open_in_editor(&location, &app);
+                    terminal.clear()?;
+                    paused.store(false, Ordering::SeqCst);
+                    if let Err(e) = result {
+                        app.status = Some(format!("Failed to run editor: {e}"));
                     }
                 }
-                KeyCode::Down | KeyCode::Char('j') => match app.current_menu_item() {
-                    MenuItem::Pattern => {
-                        app.handle_down_patterns();
-                    }
-                    MenuItem::Samples => {
-                        app.handle_down_samples();
-                    }
-                    MenuItem::Details => {
-                        app.scroll_down();
-                    }
-                },
-                KeyCode::Up | KeyCode::Char('k') => match app.current_menu_item() {
-                    MenuItem::Pattern => {
-                        app.handle_up_patterns();
-                    }
-                    MenuItem::Samples => {
-                        app.handle_up_samples();
-                    }
-                    MenuItem::Details => {
-                        app.scroll_up();
-                    }
-                },
-                KeyCode::Left | KeyCode::Char('h') => app.on_left(),
-                KeyCode::Right | KeyCode::Char('l') => app.on_right(),
-                _ => {}
+                KeyOutcome::Copy(content) => {
+                    app.status = Some(match clipboard::copy(terminal.backend_mut(), &content) {
+                        Ok(()) => format!("Copied {} bytes to clipboard", content.len()),
+                        Err(e) => format!("Copy failed: {e}"),
+                    });
+                }
             },
+            Event::Patterns(patterns) => app.push_patterns(patterns),
+            Event::Progress(progress) => app.loading = Some(progress),
+            Event::Report(meta) => app.reports.push(meta),
+            Event::Loaded(error) => app.finish_loading(error),
             Event::Tick => {}
         }
     }
@@ -183,39 +398,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-// fn render_home<'a>() -> Paragraph<'a> {
-//     let home = Paragraph::new(vec![
-//         Spans::from(vec![Span::raw("")]),
-//         Spans::from(vec![Span::raw("Welcome")]),
-//         Spans::from(vec![Span::raw("")]),
-//         Spans::from(vec![Span::raw("to")]),
-//         Spans::from(vec![Span::raw("")]),
-//         Spans::from(vec![Span::styled(
-//             "Log Pattern Viewer",
-//             Style::default().fg(Color::LightBlue),
-//         )]),
-//         Spans::from(vec![Span::raw("")]),
-//         Spans::from(vec![Span::raw("Press 'p' to access log patterns")]),
-//     ])
-//     .alignment(Alignment::Center)
-//     .block(
-//         Block::default()
-//             .borders(Borders::ALL)
-//             .style(Style::default().fg(Color::White))
-//             .title("Home")
-//             .border_type(BorderType::Plain),
-//     );
-//     home
-// }
-
-fn read_report_from_file(path: &str) -> Result<Vec<Pattern>, Error> {
-    let db_content = fs::read_to_string(path)?;
-    let mut patterns = read_report_from_str(&db_content)?;
-    patterns.sort_by(|a, b| b.count.cmp(&a.count));
-    Ok(patterns)
-}
-
-fn read_report_from_str(content: &str) -> Result<Vec<Pattern>, Error> {
-    let parsed: Vec<Pattern> = serde_json::from_str(content)?;
-    Ok(parsed)
+/// Run the editor at `location` with the terminal out of raw mode
+fn open_in_editor(location: &SourceLocation, app: &App) -> io::Result<()> {
+    disable_raw_mode()?;
+    let status = editor_command(location, &app.config.source).status();
+    enable_raw_mode()?;
+    if !status?.success() {
+        return Err(io::Error::other("editor exited with an error"));
+    }
+    Ok(())
 }
@@ -0,0 +1,220 @@
+use crate::{cost::CostModel, pattern::Pattern};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+/// Content type of the OpenMetrics text format
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Labels shared by every series of a report
+pub struct Labels {
+    pub namespace: String,
+    /// App of patterns that aren't tagged with one
+    pub app: String,
+    /// JSON field of rawlogs holding the log level
+    pub level_field: String,
+}
+
+/// Stable id of a pattern text, the FNV-1a hash of it in hex. Unlike
+/// `DefaultHasher` it doesn't change between builds, so series keep their
+/// id from one report to the next.
+pub fn pattern_id(pattern: &str) -> String {
+    let hash = pattern.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Most common level of the samples of `pattern`
+fn severity(pattern: &Pattern, level_field: &str) -> String {
+    let mut levels: HashMap<String, usize> = HashMap::new();
+    for level in pattern
+        .samples()
+        .iter()
+        .filter_map(|s| s.level(level_field))
+    {
+        *levels.entry(level).or_default() += 1;
+    }
+    levels
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+        .map_or_else(|| "unknown".to_string(), |(level, _)| level)
+}
+
+/// Escape a label value of the text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Pattern counts as OpenMetrics text, with `log_pattern_info` mapping
+/// pattern ids back to their text and, given a `cost` model, the estimated
+/// monthly cost of each series. Patterns reported twice by an app are
+/// summed into one series.
+pub fn render(patterns: &[Pattern], labels: &Labels, cost: Option<&CostModel>) -> String {
+    let mut series: Vec<(&str, String, String, usize, f64)> = Vec::new();
+    let mut index: HashMap<(&str, String), usize> = HashMap::new();
+    let mut texts: Vec<(String, &str)> = Vec::new();
+    let mut known = HashSet::new();
+    for pattern in patterns {
+        let app = pattern.app.as_deref().unwrap_or(&labels.app);
+        let id = pattern_id(&pattern.patterns);
+        let pattern_cost = cost.map_or(0.0, |cost| cost.cost(pattern));
+        match index.get(&(app, id.clone())) {
+            Some(&i) => {
+                series[i].3 += pattern.count;
+                series[i].4 += pattern_cost;
+            }
+            None => {
+                if known.insert(id.clone()) {
+                    texts.push((id.clone(), &pattern.patterns));
+                }
+                index.insert((app, id.clone()), series.len());
+                let severity = severity(pattern, &labels.level_field);
+                series.push((app, id, severity, pattern.count, pattern_cost));
+            }
+        }
+    }
+
+    let namespace = escape(&labels.namespace);
+    let mut out = String::new();
+    out.push_str("# TYPE log_pattern_count gauge\n");
+    out.push_str("# HELP log_pattern_count Number of log lines matching the pattern.\n");
+    for (app, id, severity, count, _) in &series {
+        let _ = writeln!(
+            out,
+            "log_pattern_count{{namespace=\"{}\",app=\"{}\",pattern_id=\"{}\",severity=\"{}\"}} {}",
+            namespace,
+            escape(app),
+            id,
+            escape(severity),
+            count
+        );
+    }
+    if cost.is_some() {
+        out.push_str("# TYPE log_pattern_estimated_cost gauge\n");
+        out.push_str(
+            "# HELP log_pattern_estimated_cost Estimated monthly cost of the logs of the pattern.\n",
+        );
+        for (app, id, _, _, cost) in &series {
+            let _ = writeln!(
+                out,
+                "log_pattern_estimated_cost{{namespace=\"{}\",app=\"{}\",pattern_id=\"{}\"}} {:.4}",
+                namespace,
+                escape(app),
+                id,
+                cost
+            );
+        }
+    }
+    out.push_str("# TYPE log_pattern info\n");
+    out.push_str("# HELP log_pattern Text of the pattern of a pattern_id.\n");
+    for (id, text) in &texts {
+        let _ = writeln!(
+            out,
+            "log_pattern_info{{pattern_id=\"{}\",pattern=\"{}\"}} 1",
+            id,
+            escape(text)
+        );
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Serve `text` on `GET /metrics` of `addr` until the process is stopped
+pub async fn serve(addr: SocketAddr, text: String) -> hyper::Result<()> {
+    let text = Arc::new(text);
+    let make_service = make_service_fn(move |_| {
+        let text = text.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let text = text.clone();
+                async move {
+                    let response = match (request.method(), request.uri().path()) {
+                        (&Method::GET, "/metrics") => Response::builder()
+                            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+                            .body(Body::from(text.as_str().to_string())),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty()),
+                    };
+                    Ok::<_, Infallible>(response.expect("response is valid"))
+                }
+            }))
+        }
+    });
+    Server::bind(&addr).serve(make_service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::CostConfig, pattern::pattern};
+
+    fn labels() -> Labels {
+        Labels {
+            namespace: "shop".to_string(),
+            app: "numbers".to_string(),
+            level_field: "level".to_string(),
+        }
+    }
+
+    fn report() -> Vec<Pattern> {
+        let samples =
+            r#"[{"predict":0,"date":"2022-02-12T00:00:00Z","rawlog":"{\"level\":\"ERROR\"}"}]"#;
+        let mut quoted = pattern("say \"hi\" to C:\\tmp\nnext", 2, "[]");
+        quoted.app = Some("web".to_string());
+        vec![
+            pattern("queue is full", 3, samples),
+            quoted,
+            pattern("queue is full", 4, "[]"),
+        ]
+    }
+
+    #[test]
+    fn pattern_ids_are_fnv_1a() {
+        assert_eq!(pattern_id(""), "cbf29ce484222325");
+        assert_eq!(pattern_id("a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn render_golden() {
+        let cost = CostModel::new(&CostConfig {
+            ingest_per_gb: 1.0,
+            bytes_per_line: Some(1e8),
+            ..CostConfig::default()
+        });
+        let expected = concat!(
+            "# TYPE log_pattern_count gauge\n",
+            "# HELP log_pattern_count Number of log lines matching the pattern.\n",
+            "log_pattern_count{namespace=\"shop\",app=\"numbers\",pattern_id=\"eda9979efceb4445\",severity=\"error\"} 7\n",
+            "log_pattern_count{namespace=\"shop\",app=\"web\",pattern_id=\"543b5f75cc517e5d\",severity=\"unknown\"} 2\n",
+            "# TYPE log_pattern_estimated_cost gauge\n",
+            "# HELP log_pattern_estimated_cost Estimated monthly cost of the logs of the pattern.\n",
+            "log_pattern_estimated_cost{namespace=\"shop\",app=\"numbers\",pattern_id=\"eda9979efceb4445\"} 0.7000\n",
+            "log_pattern_estimated_cost{namespace=\"shop\",app=\"web\",pattern_id=\"543b5f75cc517e5d\"} 0.2000\n",
+            "# TYPE log_pattern info\n",
+            "# HELP log_pattern Text of the pattern of a pattern_id.\n",
+            "log_pattern_info{pattern_id=\"eda9979efceb4445\",pattern=\"queue is full\"} 1\n",
+            "log_pattern_info{pattern_id=\"543b5f75cc517e5d\",pattern=\"say \\\"hi\\\" to C:\\\\tmp\\nnext\"} 1\n",
+            "# EOF\n",
+        );
+        assert_eq!(render(&report(), &labels(), cost.as_ref()), expected);
+        assert!(!render(&[], &labels(), None).contains("estimated_cost"));
+        assert!(render(&[], &labels(), None).ends_with("# EOF\n"));
+    }
+}
@@ -1,5 +1,26 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Deserializer, Serialize};
+use once_cell::sync::OnceCell;
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+
+/// Newest version of the report envelope this viewer understands
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Metadata of a report wrapped in an envelope, reports that are a bare
+/// array of patterns have none
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReportMeta {
+    pub schema_version: u32,
+    pub namespace: Option<String>,
+    pub app: Option<String>,
+    /// Period the logs were collected over, e.g. `2022-02`
+    pub period: Option<String>,
+    /// Tool that produced the report
+    pub generator: Option<String>,
+    /// Number of log lines the patterns were extracted from
+    pub total_lines: Option<u64>,
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Pattern {
@@ -8,8 +29,40 @@ pub struct Pattern {
     pub patterns: String,
     pub count: usize,
     pub percent: Option<f32>,
-    #[serde(deserialize_with = "deserialize_samples")]
-    pub samples: Vec<Sample>,
+    /// Samples as they are in the report, decoded on first access by
+    /// [`Pattern::samples`]. Reports may hold them as a string of JSON or as
+    /// a native array.
+    #[serde(rename = "samples")]
+    pub raw_samples: Box<RawValue>,
+    /// App the pattern was reported by, set when loading several apps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Counts per app once patterns of several apps are merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<AppShare>,
+    /// Undecoded samples of the patterns merged into this one
+    #[serde(skip)]
+    merged_samples: Vec<Box<RawValue>>,
+    #[serde(skip)]
+    decoded_samples: OnceCell<Result<Vec<Sample>, String>>,
+}
+
+/// JSON array of samples, unwrapping the stringified form
+fn samples_json(raw: &RawValue) -> serde_json::Result<Cow<'_, str>> {
+    if raw.get().starts_with('"') {
+        serde_json::from_str(raw.get()).map(Cow::Owned)
+    } else {
+        Ok(Cow::Borrowed(raw.get()))
+    }
+}
+
+/// Part of a merged pattern reported by one app
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AppShare {
+    pub app: String,
+    pub count: usize,
+    /// Percent of the logs of this app
+    pub percent: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -19,10 +72,135 @@ pub struct Sample {
     pub rawlog: String,
 }
 
-fn deserialize_samples<'de, D>(deserializer: D) -> Result<Vec<Sample>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let buf = String::deserialize(deserializer)?;
-    serde_json::from_str(&buf).map_err(serde::de::Error::custom)
+impl Sample {
+    /// Log level from the `field` of a JSON rawlog, lowercased
+    pub fn level(&self, field: &str) -> Option<String> {
+        let rawlog: serde_json::Value = serde_json::from_str(&self.rawlog).ok()?;
+        rawlog
+            .get(field)
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_lowercase)
+    }
+}
+
+impl Pattern {
+    /// Decoded samples, or the decoding error. Decoding happens once and
+    /// the result is cached.
+    pub fn try_samples(&self) -> Result<&[Sample], &str> {
+        self.decoded_samples
+            .get_or_init(|| {
+                let mut samples = Vec::new();
+                for raw in self.all_raw_samples() {
+                    let merged: Vec<Sample> = samples_json(raw)
+                        .and_then(|json| serde_json::from_str(&json))
+                        .map_err(|e| e.to_string())?;
+                    samples.extend(merged);
+                }
+                Ok(samples)
+            })
+            .as_ref()
+            .map(Vec::as_slice)
+            .map_err(String::as_str)
+    }
+
+    /// Move `app` into an [`AppShare`] of `apps`, so the pattern can hold
+    /// the counts of several apps
+    pub fn share_by_app(&mut self) {
+        if let Some(app) = self.app.take() {
+            self.apps.push(AppShare {
+                app,
+                count: self.count,
+                percent: None,
+            });
+        }
+    }
+
+    /// Merge the same pattern reported by another app into this one.
+    /// Samples of `other` stay undecoded until they are needed.
+    pub fn merge(&mut self, mut other: Pattern) {
+        self.share_by_app();
+        other.share_by_app();
+        self.count += other.count;
+        self.apps.extend(other.apps);
+        self.merged_samples.push(other.raw_samples);
+        self.merged_samples.extend(other.merged_samples);
+        self.decoded_samples = OnceCell::new();
+    }
+
+    /// Names of the apps reporting this pattern, joined by `,`
+    pub fn app_names(&self) -> String {
+        match &self.app {
+            Some(app) => app.clone(),
+            None => self
+                .apps
+                .iter()
+                .map(|share| share.app.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+
+    /// Decoded samples, empty when they can't be decoded
+    pub fn samples(&self) -> &[Sample] {
+        self.try_samples().unwrap_or(&[])
+    }
+
+    /// Average JSON size of a sample, measured without decoding the
+    /// samples. `None` when there are none or they aren't a JSON array.
+    pub fn sample_size(&self) -> Option<f64> {
+        let mut bytes = 0;
+        let mut samples = 0;
+        for raw in self.all_raw_samples() {
+            let json = samples_json(raw).ok()?;
+            samples += serde_json::from_str::<Vec<IgnoredAny>>(&json).ok()?.len();
+            bytes += json.len();
+        }
+        (samples > 0).then(|| bytes as f64 / samples as f64)
+    }
+
+    fn all_raw_samples(&self) -> impl Iterator<Item = &RawValue> {
+        std::iter::once(&*self.raw_samples).chain(self.merged_samples.iter().map(|raw| &**raw))
+    }
+}
+
+/// Pattern of `count` lines for tests, with `samples` in the stringified
+/// form and no percent
+#[cfg(test)]
+pub(crate) fn pattern(text: &str, count: usize, samples: &str) -> Pattern {
+    serde_json::from_value(serde_json::json!({
+        "patterns": text,
+        "count": count,
+        "percent": null,
+        "samples": samples,
+    }))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &str =
+        r#"[{"predict":0,"date":"2022-02-12T00:00:00Z","rawlog":"queue is full"}]"#;
+
+    #[test]
+    fn samples_stay_undecoded_until_asked_for() {
+        let pattern = pattern("queue is full", 1, SAMPLES);
+        assert_eq!(pattern.sample_size(), Some(SAMPLES.len() as f64));
+        assert!(pattern.decoded_samples.get().is_none());
+        assert_eq!(pattern.samples()[0].rawlog, "queue is full");
+    }
+
+    #[test]
+    fn stringified_and_native_samples_decode_alike() {
+        // from text, as a `Value` would reorder the keys
+        let json = format!(r#"{{"patterns":"queue","count":1,"samples":{}}}"#, SAMPLES);
+        let native: Pattern = serde_json::from_str(&json).unwrap();
+        assert_eq!(native.raw_samples.get(), SAMPLES);
+        let mut merged = pattern("queue", 1, SAMPLES);
+        merged.merge(native);
+        let dates: Vec<_> = merged.samples().iter().map(|sample| sample.date).collect();
+        assert_eq!(dates.len(), 2);
+        assert_eq!(dates[0], dates[1]);
+    }
 }
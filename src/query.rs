@@ -0,0 +1,679 @@
+//! Queries filtering patterns and their samples, e.g.
+//! `count > 100 and pattern ~ /timeout/ and sample.level = "error"`.
+//!
+//! A query combines comparisons with `and`, `or`, `not` and parentheses.
+//! Fields are `count`, `percent`, `pattern`, `app` and the `sample.` fields
+//! `date`, `predict`, `rawlog` or any field of a JSON rawlog. Operators are
+//! `=`, `!=`, `<`, `<=`, `>`, `>=` and the regex matches `~` and `!~`.
+//! Values are numbers, dates, `"strings"`, `/regexes/` with an optional `i`
+//! flag, or bare words.
+
+use crate::pattern::{Pattern, Sample};
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+use serde_json::Value;
+use std::{cmp::Ordering, fmt};
+use thiserror::Error;
+
+/// Why a query couldn't be parsed, `position` is a byte offset in the query
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{message} at column {}", .position + 1)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+fn error<T>(position: usize, message: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError {
+        position,
+        message: message.into(),
+    })
+}
+
+/// A parsed query
+#[derive(Debug)]
+pub struct Query {
+    text: String,
+    expr: Expr,
+    /// Whether any comparison tests a sample field
+    tests_samples: bool,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Comparison),
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub field: Field,
+    pub op: Op,
+    pub value: Literal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Count,
+    Percent,
+    Pattern,
+    App,
+    SampleDate,
+    SamplePredict,
+    SampleRawlog,
+    /// Path to a field of a JSON rawlog
+    SampleField(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+/// A value typed by the field it's compared with
+#[derive(Debug)]
+pub enum Literal {
+    Number(f64),
+    Date(DateTime<Utc>),
+    Text(String),
+    Regex(Regex),
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, ParseError> {
+        let tokens = lex(text)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: text.len(),
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return error(
+                token.position,
+                "expected `and`, `or` or the end of the query",
+            );
+        }
+        Ok(Query {
+            text: text.trim().to_string(),
+            tests_samples: expr.tests_samples(),
+            expr,
+        })
+    }
+
+    /// Whether `pattern` matches. Queries testing samples need at least one
+    /// matching sample.
+    pub fn matches(&self, pattern: &Pattern) -> bool {
+        let samples = pattern.samples();
+        if !self.tests_samples || samples.is_empty() {
+            return self.expr.eval(pattern, None);
+        }
+        samples
+            .iter()
+            .any(|sample| self.expr.eval(pattern, Some(sample)))
+    }
+
+    /// Whether `sample` of `pattern` matches, every sample does when the
+    /// query doesn't test samples
+    pub fn matches_sample(&self, pattern: &Pattern, sample: &Sample) -> bool {
+        !self.tests_samples || self.expr.eval(pattern, Some(sample))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Expr {
+    fn tests_samples(&self) -> bool {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.tests_samples() || b.tests_samples(),
+            Expr::Not(a) => a.tests_samples(),
+            Expr::Compare(comparison) => comparison.field.is_sample(),
+        }
+    }
+
+    fn eval(&self, pattern: &Pattern, sample: Option<&Sample>) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(pattern, sample) && b.eval(pattern, sample),
+            Expr::Or(a, b) => a.eval(pattern, sample) || b.eval(pattern, sample),
+            Expr::Not(a) => !a.eval(pattern, sample),
+            Expr::Compare(comparison) => comparison.eval(pattern, sample),
+        }
+    }
+}
+
+impl Field {
+    fn is_sample(&self) -> bool {
+        matches!(
+            self,
+            Field::SampleDate | Field::SamplePredict | Field::SampleRawlog | Field::SampleField(_)
+        )
+    }
+
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "count" => Field::Count,
+            "percent" => Field::Percent,
+            "pattern" | "patterns" => Field::Pattern,
+            "app" => Field::App,
+            "sample.date" => Field::SampleDate,
+            "sample.predict" => Field::SamplePredict,
+            "sample.rawlog" => Field::SampleRawlog,
+            _ => {
+                let path = name.strip_prefix("sample.")?;
+                if path.split('.').any(str::is_empty) {
+                    return None;
+                }
+                Field::SampleField(path.split('.').map(str::to_string).collect())
+            }
+        })
+    }
+}
+
+impl Op {
+    fn apply(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+
+    fn is_ordering(self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge)
+    }
+}
+
+impl Comparison {
+    fn eval(&self, pattern: &Pattern, sample: Option<&Sample>) -> bool {
+        if self.field.is_sample() && sample.is_none() {
+            return false;
+        }
+        match (&self.field, sample) {
+            (Field::Count, _) => self.number(pattern.count as f64),
+            (Field::Percent, _) => self.number(f64::from(pattern.percent.unwrap_or(0.0))),
+            (Field::Pattern, _) => self.text(&pattern.patterns),
+            (Field::App, _) => self.text(&pattern.app_names()),
+            (Field::SampleDate, Some(sample)) => match &self.value {
+                Literal::Date(date) => self.op.apply(sample.date.cmp(date)),
+                _ => self.text(&sample.date.to_rfc3339()),
+            },
+            (Field::SamplePredict, Some(sample)) => self.number(f64::from(sample.predict)),
+            (Field::SampleRawlog, Some(sample)) => self.text(&sample.rawlog),
+            (Field::SampleField(path), Some(sample)) => {
+                let json: Value = match serde_json::from_str(&sample.rawlog) {
+                    Ok(json) => json,
+                    Err(_) => return false,
+                };
+                match path.iter().try_fold(&json, |value, key| value.get(key)) {
+                    Some(Value::Number(n)) => match (&self.value, n.as_f64()) {
+                        (Literal::Number(_), Some(n)) => self.number(n),
+                        _ => self.text(&n.to_string()),
+                    },
+                    Some(Value::String(s)) => self.text(s),
+                    Some(Value::Bool(b)) => self.text(&b.to_string()),
+                    Some(Value::Null) | None => false,
+                    Some(other) => self.text(&other.to_string()),
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn number(&self, n: f64) -> bool {
+        match &self.value {
+            Literal::Number(value) => n
+                .partial_cmp(value)
+                .is_some_and(|ordering| self.op.apply(ordering)),
+            Literal::Regex(regex) => self.regex(regex, &n.to_string()),
+            _ => false,
+        }
+    }
+
+    fn text(&self, s: &str) -> bool {
+        match &self.value {
+            Literal::Regex(regex) => self.regex(regex, s),
+            Literal::Number(value) => s
+                .parse::<f64>()
+                .ok()
+                .and_then(|n| n.partial_cmp(value))
+                .is_some_and(|ordering| self.op.apply(ordering)),
+            Literal::Text(value) => self.op.apply(s.cmp(value.as_str())),
+            Literal::Date(date) => s
+                .parse::<DateTime<Utc>>()
+                .is_ok_and(|d| self.op.apply(d.cmp(date))),
+        }
+    }
+
+    fn regex(&self, regex: &Regex, s: &str) -> bool {
+        regex.is_match(s) == (self.op == Op::Match)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Text(String),
+    /// Regex source with its flags applied
+    Regex(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | ':' | '+')
+}
+
+fn lex(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        let kind = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => {
+                chars.next();
+                TokenKind::Open
+            }
+            ')' => {
+                chars.next();
+                TokenKind::Close
+            }
+            '"' | '/' => {
+                chars.next();
+                let delimiter = c;
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        c if c == delimiter => {
+                            closed = true;
+                            break;
+                        }
+                        // regexes keep their escapes, except for the delimiter
+                        '\\' => match chars.next() {
+                            Some((_, e)) if e == delimiter || delimiter == '"' => value.push(e),
+                            Some((_, e)) => {
+                                value.push('\\');
+                                value.push(e);
+                            }
+                            None => break,
+                        },
+                        c => value.push(c),
+                    }
+                }
+                if !closed {
+                    let what = if delimiter == '"' { "string" } else { "regex" };
+                    return error(position, format!("unterminated {}", what));
+                }
+                if delimiter == '"' {
+                    TokenKind::Text(value)
+                } else {
+                    // a trailing `i` makes the regex case insensitive
+                    if let Some(&(_, 'i')) = chars.peek() {
+                        chars.next();
+                        value = format!("(?i){}", value);
+                    }
+                    TokenKind::Regex(value)
+                }
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, c)| c);
+                let (op, long) = match (c, next) {
+                    ('=', Some('=')) => (Op::Eq, true),
+                    ('=', _) => (Op::Eq, false),
+                    ('!', Some('=')) => (Op::Ne, true),
+                    ('!', Some('~')) => (Op::NotMatch, true),
+                    ('<', Some('=')) => (Op::Le, true),
+                    ('<', _) => (Op::Lt, false),
+                    ('>', Some('=')) => (Op::Ge, true),
+                    ('>', _) => (Op::Gt, false),
+                    ('~', _) => (Op::Match, false),
+                    _ => return error(position, "expected `!=` or `!~`"),
+                };
+                if long {
+                    chars.next();
+                }
+                TokenKind::Op(op)
+            }
+            c if is_word_char(c) => {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !is_word_char(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                TokenKind::Word(word)
+            }
+            c => return error(position, format!("unexpected `{}`", c)),
+        };
+        tokens.push(Token { kind, position });
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, `or` binds looser than `and`, which binds
+/// looser than `not`
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// Position reported for errors at the end of the query
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Word(word),
+                ..
+            }) if word.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let token = match self.advance() {
+            Some(token) => token,
+            None => return error(self.end, "expected a comparison"),
+        };
+        match token.kind {
+            TokenKind::Open => {
+                let expr = self.or()?;
+                match self.advance() {
+                    Some(Token {
+                        kind: TokenKind::Close,
+                        ..
+                    }) => Ok(expr),
+                    Some(token) => error(token.position, "expected `)`"),
+                    None => error(self.end, "expected `)`"),
+                }
+            }
+            TokenKind::Word(name) => {
+                let field = match Field::parse(&name) {
+                    Some(field) => field,
+                    None => {
+                        return error(
+                            token.position,
+                            format!(
+                                "unknown field `{}`, expected count, percent, pattern, app or sample.<field>",
+                                name
+                            ),
+                        )
+                    }
+                };
+                let op = match self.advance() {
+                    Some(Token {
+                        kind: TokenKind::Op(op),
+                        ..
+                    }) => op,
+                    Some(token) => return error(token.position, "expected an operator"),
+                    None => return error(self.end, "expected an operator"),
+                };
+                let value = match self.advance() {
+                    Some(token) => literal(&field, op, token)?,
+                    None => return error(self.end, "expected a value"),
+                };
+                Ok(Expr::Compare(Comparison { field, op, value }))
+            }
+            _ => error(token.position, "expected a field or `(`"),
+        }
+    }
+}
+
+/// Type the value `token` for comparing `field` with `op`
+fn literal(field: &Field, op: Op, token: Token) -> Result<Literal, ParseError> {
+    let position = token.position;
+    let value = match token.kind {
+        TokenKind::Word(word) | TokenKind::Text(word) => word,
+        TokenKind::Regex(source) if matches!(op, Op::Match | Op::NotMatch) => source,
+        TokenKind::Regex(_) => return error(position, "regexes need `~` or `!~`"),
+        _ => return error(position, "expected a value"),
+    };
+
+    if matches!(op, Op::Match | Op::NotMatch) {
+        return match Regex::new(&value) {
+            Ok(regex) => Ok(Literal::Regex(regex)),
+            Err(e) => error(position, format!("invalid regex: {}", e)),
+        };
+    }
+    match field {
+        Field::Count | Field::Percent | Field::SamplePredict => match value.parse() {
+            Ok(n) => Ok(Literal::Number(n)),
+            Err(_) => error(position, format!("expected a number, found `{}`", value)),
+        },
+        Field::SampleDate => match parse_date(&value) {
+            Some(date) => Ok(Literal::Date(date)),
+            None => error(
+                position,
+                format!("expected a date like 2022-02-12, found `{}`", value),
+            ),
+        },
+        Field::Pattern | Field::App | Field::SampleRawlog if op.is_ordering() => {
+            error(position, "text can only be compared with =, !=, ~ and !~")
+        }
+        Field::SampleField(_) => Ok(match value.parse() {
+            Ok(n) => Literal::Number(n),
+            Err(_) => Literal::Text(value),
+        }),
+        _ => Ok(Literal::Text(value)),
+    }
+}
+
+/// A RFC 3339 date time, or a day at midnight UTC
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = value.parse::<DateTime<Utc>>() {
+        return Some(date);
+    }
+    let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some(DateTime::from_naive_utc_and_offset(
+        day.and_hms_opt(0, 0, 0)?,
+        Utc,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::pattern;
+    use serde_json::json;
+
+    fn timeout() -> Pattern {
+        let samples = json!([
+            {
+                "predict": 0,
+                "date": "2022-02-13T14:33:19Z",
+                "rawlog": r#"{"level":"error","http":{"code":504}}"#,
+            },
+            {
+                "predict": 1,
+                "date": "2022-02-11T08:00:00Z",
+                "rawlog": r#"{"level":"info","http":{"code":200}}"#,
+            },
+        ]);
+        let mut timeout = pattern("request Timeout after Number ms", 150, &samples.to_string());
+        timeout.percent = Some(12.5);
+        timeout
+    }
+
+    fn matches(query: &str) -> bool {
+        Query::parse(query).unwrap().matches(&timeout())
+    }
+
+    fn parse_error(query: &str) -> ParseError {
+        Query::parse(query).unwrap_err()
+    }
+
+    #[test]
+    fn compares_pattern_fields() {
+        assert!(matches("count > 100"));
+        assert!(matches("count = 150"));
+        assert!(!matches("count < 150"));
+        assert!(matches("percent >= 12.5"));
+        assert!(matches(r#"pattern = "request Timeout after Number ms""#));
+        assert!(!matches("pattern != \"request Timeout after Number ms\""));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // `(count > 100 or count > 0) and percent > 50` would not match
+        assert!(matches("count > 100 or count > 0 and percent > 50"));
+        assert!(!matches("count < 0 and count > 100 or percent > 50"));
+        assert!(!matches("count > 100 and (count < 0 or percent > 50)"));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        // `not (count < 100 and percent > 50)` would match
+        assert!(!matches("not count < 100 and percent > 50"));
+        assert!(!matches("not (count > 100 and percent > 10)"));
+        assert!(matches("not not count > 100"));
+        assert!(matches("NOT count < 100"));
+    }
+
+    #[test]
+    fn regexes_match_with_flags_and_escapes() {
+        assert!(matches("pattern ~ /timeout/i"));
+        assert!(!matches("pattern ~ /timeout/"));
+        assert!(matches("pattern !~ /^health/"));
+        assert!(matches(r"pattern ~ /after \w+ ms$/"));
+        assert!(matches(r"sample.rawlog ~ /\{.level.:.error./"));
+        assert_eq!(
+            parse_error("pattern = /timeout/").message,
+            "regexes need `~` or `!~`"
+        );
+    }
+
+    #[test]
+    fn dates_are_days_or_rfc3339() {
+        assert!(matches("sample.date >= 2022-02-13"));
+        assert!(!matches("sample.date > 2022-02-14"));
+        assert!(matches("sample.date < 2022-02-11T09:00:00Z"));
+        assert_eq!(
+            parse_date("2022-02-12"),
+            Some("2022-02-12T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            parse_error("sample.date > yesterday").message,
+            "expected a date like 2022-02-12, found `yesterday`"
+        );
+    }
+
+    #[test]
+    fn sample_fields_need_one_matching_sample() {
+        assert!(matches(r#"sample.level = "error""#));
+        assert!(matches("sample.http.code >= 500"));
+        assert!(!matches(
+            r#"sample.level = "error" and sample.http.code = 200"#
+        ));
+        assert!(!matches("sample.missing = 1"));
+    }
+
+    #[test]
+    fn only_matching_samples_are_shown() {
+        let query = Query::parse(r#"sample.level = "error""#).unwrap();
+        let pattern = timeout();
+        let samples = pattern.samples();
+        assert!(query.matches_sample(&pattern, &samples[0]));
+        assert!(!query.matches_sample(&pattern, &samples[1]));
+        let query = Query::parse("count > 1").unwrap();
+        assert!(query.matches_sample(&pattern, &samples[1]));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let error = parse_error("count > 1 and bogus = 2");
+        assert_eq!(error.position, 14);
+        assert!(error.message.starts_with("unknown field `bogus`"));
+        assert_eq!(parse_error("count > many").position, 8);
+        assert_eq!(parse_error("count 5").position, 6);
+        assert_eq!(parse_error(r#"pattern = "open"#).position, 10);
+        assert_eq!(parse_error("pattern ~ /open").message, "unterminated regex");
+        assert_eq!(parse_error("count ! 1").message, "expected `!=` or `!~`");
+        assert_eq!(parse_error("pattern < abc").position, 10);
+        assert_eq!(parse_error("count > 1 count").position, 10);
+        assert_eq!(parse_error("pattern ~ /(/").position, 10);
+    }
+
+    #[test]
+    fn errors_at_the_end_point_past_the_query() {
+        let error = parse_error("(count > 1");
+        assert_eq!(
+            (error.position, error.message.as_str()),
+            (10, "expected `)`")
+        );
+        assert_eq!(parse_error("count >").position, 7);
+        assert_eq!(
+            parse_error("count > 1 and").message,
+            "expected a comparison"
+        );
+        assert_eq!(
+            parse_error("").to_string(),
+            "expected a comparison at column 1"
+        );
+    }
+}
@@ -1,9 +1,23 @@
 use anyhow::Result;
-use rusoto_s3::{GetObjectRequest, S3Client, S3};
+use rusoto_core::{request::HttpClient, RusotoError};
+use rusoto_credential::ProfileProvider;
+use rusoto_s3::StreamingBody;
+use rusoto_s3::{GetObjectRequest, ListObjectsV2Request, S3Client, S3};
 use tokio::io::AsyncReadExt;
 
-const BUCKET: &str = "nwlogs";
+pub const BUCKET: &str = "nwlogs";
 const REPORT_PATH: &str = "log-patterns-reports";
+const DEFAULT_REGION: &str = "cn-northwest-1";
+
+/// Build s3 client from aws profile and region, defaults are used when not set
+pub fn client(profile: Option<&str>, region: Option<&str>) -> Result<S3Client> {
+    let profile = match profile {
+        Some(profile) => ProfileProvider::with_default_credentials(profile)?,
+        None => ProfileProvider::new()?,
+    };
+    let region = region.unwrap_or(DEFAULT_REGION).parse()?;
+    Ok(S3Client::new_with(HttpClient::new()?, profile, region))
+}
 
 /// read report file from s3 bucket file
 pub async fn read_report_file(
@@ -24,6 +38,50 @@ pub async fn read_report_file_from_key(s3: &S3Client, key: &str) -> Result<Strin
     Ok(String::from_utf8_lossy(&buf).to_string())
 }
 
+/// Object opened by [`open_object`]
+pub enum Fetched {
+    Body {
+        body: StreamingBody,
+        /// Size of the object if known
+        len: Option<u64>,
+        etag: Option<String>,
+    },
+    /// The object still has the etag we asked with
+    NotModified,
+}
+
+/// Open object contents under bucket and use a key for streaming. With an
+/// `etag` the object is only sent when it changed.
+pub async fn open_object(
+    s3: &S3Client,
+    bucket: &str,
+    key: &str,
+    etag: Option<&str>,
+) -> Result<Fetched> {
+    let output = s3
+        .get_object(GetObjectRequest {
+            bucket: bucket.into(),
+            key: key.into(),
+            if_none_match: etag.map(str::to_string),
+            ..Default::default()
+        })
+        .await;
+    let output = match output {
+        Err(RusotoError::Unknown(response)) if response.status.as_u16() == 304 => {
+            return Ok(Fetched::NotModified)
+        }
+        output => output?,
+    };
+    let body = output
+        .body
+        .ok_or_else(|| anyhow::anyhow!("empty body for s3://{bucket}/{key}"))?;
+    Ok(Fetched::Body {
+        body,
+        len: output.content_length.map(|len| len as u64),
+        etag: output.e_tag,
+    })
+}
+
 /// Get object contents under bucket and use a key
 pub async fn get_object(s3: &S3Client, bucket: &str, key: &str) -> Result<Vec<u8>> {
     let body = s3
@@ -47,3 +105,48 @@ pub async fn get_object(s3: &S3Client, bucket: &str, key: &str) -> Result<Vec<u8
 pub fn report_file_key(namespace: &str, app: &str, year: i32, month: i32) -> String {
     format!("{REPORT_PATH}/{namespace}/{app}/{year}/{month:0>2}/report.json")
 }
+
+/// Apps of namespace with a report for year and month in the s3 bucket
+pub async fn list_report_apps(
+    s3: &S3Client,
+    namespace: &str,
+    year: i32,
+    month: i32,
+) -> Result<Vec<String>> {
+    let prefix = format!("{REPORT_PATH}/{namespace}/");
+    let mut apps = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let output = s3
+            .list_objects_v2(ListObjectsV2Request {
+                bucket: BUCKET.into(),
+                prefix: Some(prefix.clone()),
+                continuation_token,
+                ..Default::default()
+            })
+            .await?;
+        for object in output.contents.unwrap_or_default() {
+            let key = object.key.unwrap_or_default();
+            if let Some(app) = report_file_app(&key, namespace) {
+                if key == report_file_key(namespace, app, year, month) {
+                    apps.push(app.to_string());
+                }
+            }
+        }
+        continuation_token = output.next_continuation_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    Ok(apps)
+}
+
+/// App of a report file key, the inverse of [`report_file_key`]
+pub fn report_file_app<'a>(key: &'a str, namespace: &str) -> Option<&'a str> {
+    key.strip_prefix(REPORT_PATH)?
+        .strip_prefix('/')?
+        .strip_prefix(namespace)?
+        .strip_prefix('/')?
+        .split('/')
+        .next()
+}
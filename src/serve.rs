@@ -0,0 +1,528 @@
+use crate::{
+    cost::CostModel,
+    metrics::pattern_id,
+    pattern::{Pattern, ReportMeta, Sample},
+    query::Query,
+    redact::Redactor,
+};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+/// Web viewer served on `/`
+const INDEX: &str = include_str!("serve.html");
+
+/// Page size of pattern lists when the request doesn't set `limit`
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 1000;
+
+/// A report loaded for serving
+pub struct Report {
+    pub name: String,
+    pub meta: Option<ReportMeta>,
+    patterns: Vec<Pattern>,
+    total: usize,
+}
+
+impl Report {
+    pub fn new(name: String, mut patterns: Vec<Pattern>, meta: Option<ReportMeta>) -> Report {
+        let total: usize = patterns.iter().map(|p| p.count).sum();
+        for pattern in patterns.iter_mut() {
+            pattern.percent = Some(if total == 0 {
+                0.0
+            } else {
+                pattern.count as f32 / total as f32 * 100.0
+            });
+        }
+        Report {
+            name,
+            meta,
+            patterns,
+            total,
+        }
+    }
+
+    /// Counts per pattern id, patterns reported twice are summed
+    fn counts(&self) -> HashMap<String, (&str, usize)> {
+        let mut counts: HashMap<String, (&str, usize)> = HashMap::new();
+        for pattern in &self.patterns {
+            counts
+                .entry(pattern_id(&pattern.patterns))
+                .or_insert((&pattern.patterns, 0))
+                .1 += pattern.count;
+        }
+        counts
+    }
+}
+
+/// What requests are served from
+struct State {
+    reports: Vec<Report>,
+    redactor: Redactor,
+    cost: Option<CostModel>,
+}
+
+#[derive(Serialize)]
+struct ReportSummary<'a> {
+    name: &'a str,
+    meta: Option<&'a ReportMeta>,
+    patterns: usize,
+    total: usize,
+    /// Estimated monthly cost of all patterns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct PatternSummary<'a> {
+    id: String,
+    patterns: &'a str,
+    count: usize,
+    percent: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app: Option<&'a str>,
+    samples: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+impl<'a> PatternSummary<'a> {
+    fn new(pattern: &'a Pattern, cost: Option<&CostModel>) -> Self {
+        PatternSummary {
+            id: pattern_id(&pattern.patterns),
+            patterns: &pattern.patterns,
+            count: pattern.count,
+            percent: pattern.percent,
+            app: pattern.app.as_deref(),
+            samples: pattern.samples().len(),
+            cost: cost.map(|cost| cost.cost(pattern)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Page<T> {
+    total: usize,
+    offset: usize,
+    items: Vec<T>,
+}
+
+#[derive(Serialize)]
+struct DiffRow<'a> {
+    id: String,
+    patterns: &'a str,
+    from: usize,
+    to: usize,
+    delta: i64,
+    status: &'static str,
+}
+
+/// Serve the API and web viewer for `reports` on `addr` until the process
+/// is stopped. Samples are served through `redactor`, so they are redacted
+/// unless redaction is disabled in the config. Patterns get their estimated
+/// cost when there's a `cost` model.
+pub async fn serve(
+    addr: SocketAddr,
+    reports: Vec<Report>,
+    redactor: Redactor,
+    cost: Option<CostModel>,
+) -> hyper::Result<()> {
+    let state = Arc::new(State {
+        reports,
+        redactor,
+        cost,
+    });
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, request)) }
+            }))
+        }
+    });
+    Server::bind(&addr).serve(make_service).await
+}
+
+fn handle(state: &State, request: Request<Body>) -> Response<Body> {
+    let (reports, redactor, cost) = (&state.reports, &state.redactor, state.cost.as_ref());
+    if request.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+    }
+    let query: HashMap<String, String> =
+        form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+            .into_owned()
+            .collect();
+    // report names come from file names, the web viewer encodes them
+    let segments: Result<Vec<Cow<str>>, _> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8())
+        .collect();
+    let segments = match &segments {
+        Ok(segments) => segments.iter().map(Cow::as_ref).collect::<Vec<&str>>(),
+        Err(_) => return error(StatusCode::BAD_REQUEST, "path is not UTF-8"),
+    };
+
+    let find = |name: &str| reports.iter().find(|report| report.name == name);
+    match segments.as_slice() {
+        [] => Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(INDEX))
+            .expect("response is valid"),
+        ["api", "reports"] => json(
+            &reports
+                .iter()
+                .map(|report| ReportSummary {
+                    name: &report.name,
+                    meta: report.meta.as_ref(),
+                    patterns: report.patterns.len(),
+                    total: report.total,
+                    cost: cost.map(|cost| report.patterns.iter().map(|p| cost.cost(p)).sum()),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ["api", "reports", name, "patterns"] => match find(name) {
+            Some(report) => list_patterns(report, &query, cost),
+            None => error(StatusCode::NOT_FOUND, "no such report"),
+        },
+        ["api", "reports", name, "patterns", id, "samples"] => {
+            let pattern = find(name).and_then(|report| {
+                report
+                    .patterns
+                    .iter()
+                    .find(|pattern| pattern_id(&pattern.patterns) == *id)
+            });
+            match pattern.map(Pattern::try_samples) {
+                Some(Ok(samples)) => json(
+                    &samples
+                        .iter()
+                        .map(|sample| Sample {
+                            rawlog: redactor.redact(&sample.rawlog).into_owned(),
+                            ..sample.clone()
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                Some(Err(e)) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+                None => error(StatusCode::NOT_FOUND, "no such pattern"),
+            }
+        }
+        ["api", "diff"] => {
+            let from = query.get("from").and_then(|name| find(name));
+            let to = query.get("to").and_then(|name| find(name));
+            match (from, to) {
+                (Some(from), Some(to)) => diff(from, to, &query),
+                _ => error(StatusCode::BAD_REQUEST, "`from` and `to` must name reports"),
+            }
+        }
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+/// A page of the patterns of `report`, filtered by the `q` substring and the
+/// `filter` query, sorted by `sort` (`count`, `percent` or `pattern`) in `order`
+fn list_patterns(
+    report: &Report,
+    query: &HashMap<String, String>,
+    cost: Option<&CostModel>,
+) -> Response<Body> {
+    let search = query.get("q").map(|q| q.to_lowercase());
+    let filter = match query.get("filter").map(|filter| Query::parse(filter)) {
+        Some(Ok(filter)) => Some(filter),
+        Some(Err(e)) => return error(StatusCode::BAD_REQUEST, &format!("invalid filter: {}", e)),
+        None => None,
+    };
+    let mut patterns: Vec<&Pattern> = report
+        .patterns
+        .iter()
+        .filter(|pattern| {
+            search
+                .as_deref()
+                .is_none_or(|q| pattern.patterns.to_lowercase().contains(q))
+        })
+        .filter(|pattern| filter.as_ref().is_none_or(|filter| filter.matches(pattern)))
+        .collect();
+
+    let compare: fn(&&Pattern, &&Pattern) -> Ordering = match query.get("sort").map(String::as_str)
+    {
+        None | Some("count") | Some("percent") => |a, b| a.count.cmp(&b.count),
+        Some("pattern") => |a, b| a.patterns.cmp(&b.patterns),
+        Some(_) => return error(StatusCode::BAD_REQUEST, "unknown sort"),
+    };
+    match query.get("order").map(String::as_str) {
+        Some("asc") => patterns.sort_by(compare),
+        None | Some("desc") => patterns.sort_by(|a, b| compare(b, a)),
+        Some(_) => return error(StatusCode::BAD_REQUEST, "order must be asc or desc"),
+    }
+
+    let (offset, limit) = match paging(query) {
+        Ok(paging) => paging,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+    };
+    json(&Page {
+        total: patterns.len(),
+        offset,
+        items: patterns
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|pattern| PatternSummary::new(pattern, cost))
+            .collect(),
+    })
+}
+
+/// Patterns whose counts differ between `from` and `to`, largest change
+/// first
+fn diff(from: &Report, to: &Report, query: &HashMap<String, String>) -> Response<Body> {
+    let before = from.counts();
+    let after = to.counts();
+    let mut rows: Vec<DiffRow> = after
+        .iter()
+        .map(|(id, &(patterns, count))| {
+            let old = before.get(id).map_or(0, |(_, count)| *count);
+            (id, patterns, old, count)
+        })
+        .chain(
+            before
+                .iter()
+                .filter(|(id, _)| !after.contains_key(*id))
+                .map(|(id, &(patterns, count))| (id, patterns, count, 0)),
+        )
+        .map(|(id, patterns, from, to)| DiffRow {
+            id: id.clone(),
+            patterns,
+            from,
+            to,
+            delta: to as i64 - from as i64,
+            status: match (from, to) {
+                (0, _) => "new",
+                (_, 0) => "gone",
+                (from, to) if from == to => "same",
+                _ => "changed",
+            },
+        })
+        .filter(|row| row.status != "same")
+        .collect();
+    rows.sort_by_key(|row| (Reverse(row.delta.abs()), row.id.clone()));
+
+    let (offset, limit) = match paging(query) {
+        Ok(paging) => paging,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+    };
+    json(&Page {
+        total: rows.len(),
+        offset,
+        items: rows.into_iter().skip(offset).take(limit).collect(),
+    })
+}
+
+/// `offset` and `limit` of the query, or what's wrong with them
+fn paging(query: &HashMap<String, String>) -> Result<(usize, usize), String> {
+    let number = |name: &str, default: usize| match query.get(name) {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| format!("{} must be a number", name)),
+        None => Ok(default),
+    };
+    Ok((
+        number("offset", 0)?,
+        number("limit", DEFAULT_LIMIT)?.min(MAX_LIMIT),
+    ))
+}
+
+fn json<T: Serialize + ?Sized>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("response is valid"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "error": message }).to_string(),
+        ))
+        .expect("response is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::RedactConfig, pattern::pattern};
+    use serde_json::{json, Value};
+
+    fn state() -> State {
+        let samples = json!([
+            { "predict": 0, "date": "2022-02-12T00:00:00Z", "rawlog": "mail bob@example.com" },
+        ]);
+        let february = vec![
+            pattern("queue is full", 60, &samples.to_string()),
+            pattern("Disk full", 30, "[]"),
+            pattern("retry Number", 10, "[]"),
+        ];
+        let march = vec![
+            pattern("queue is full", 80, "[]"),
+            pattern("Disk full", 30, "[]"),
+            pattern("cache miss", 5, "[]"),
+        ];
+        State {
+            reports: vec![
+                Report::new("2022-02 numbers".to_string(), february, None),
+                Report::new("2022-03".to_string(), march, None),
+            ],
+            redactor: Redactor::new(&RedactConfig::default()).unwrap(),
+            cost: None,
+        }
+    }
+
+    fn request(method: Method, uri: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(&state(), request);
+        let status = response.status();
+        let body = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(hyper::body::to_bytes(response.into_body()))
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn get(uri: &str) -> (StatusCode, Value) {
+        request(Method::GET, uri)
+    }
+
+    /// Pattern texts of a page
+    fn texts(page: &Value) -> Vec<&str> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["patterns"].as_str().unwrap())
+            .collect()
+    }
+
+    const FEBRUARY: &str = "/api/reports/2022-02%20numbers/patterns";
+
+    #[test]
+    fn reports_are_listed_with_their_totals() {
+        let (status, reports) = get("/api/reports");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reports[0]["name"], "2022-02 numbers");
+        assert_eq!(reports[0]["patterns"], 3);
+        assert_eq!(reports[0]["total"], 100);
+        assert_eq!(reports[1]["total"], 115);
+    }
+
+    #[test]
+    fn patterns_are_sorted_by_count_by_default() {
+        let (status, page) = get(FEBRUARY);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["total"], 3);
+        assert_eq!(texts(&page), ["queue is full", "Disk full", "retry Number"]);
+        let percent = page["items"][0]["percent"].as_f64().unwrap();
+        assert!((percent - 60.0).abs() < 1e-4);
+        assert_eq!(page["items"][0]["samples"], 1);
+    }
+
+    #[test]
+    fn patterns_can_be_searched_filtered_sorted_and_paged() {
+        let (_, page) = get(&format!("{}?q=FULL&sort=pattern&order=asc", FEBRUARY));
+        assert_eq!(texts(&page), ["Disk full", "queue is full"]);
+        let (_, page) = get(&format!("{}?filter=count%20%3C%2050", FEBRUARY));
+        assert_eq!(texts(&page), ["Disk full", "retry Number"]);
+        let (_, page) = get(&format!("{}?order=asc&offset=1&limit=1", FEBRUARY));
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["offset"], 1);
+        assert_eq!(texts(&page), ["Disk full"]);
+    }
+
+    #[test]
+    fn bad_pattern_queries_are_rejected() {
+        for query in [
+            "sort=size",
+            "order=up",
+            "limit=ten",
+            "offset=-1",
+            "filter=count%20%3E",
+        ] {
+            let (status, body) = get(&format!("{}?{}", FEBRUARY, query));
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+            assert!(body["error"].is_string(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn samples_are_redacted() {
+        let id = pattern_id("queue is full");
+        let (status, samples) = get(&format!("{}/{}/samples", FEBRUARY, id));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(samples[0]["rawlog"], "mail [REDACTED:email]");
+        let (status, _) = get(&format!("{}/{}/samples", FEBRUARY, pattern_id("nope")));
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn diff_lists_changed_patterns_by_largest_change() {
+        let (status, page) = get("/api/diff?from=2022-02%20numbers&to=2022-03");
+        assert_eq!(status, StatusCode::OK);
+        let rows: Vec<(&str, &str, i64)> = page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row["patterns"].as_str().unwrap(),
+                    row["status"].as_str().unwrap(),
+                    row["delta"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("queue is full", "changed", 20),
+                ("retry Number", "gone", -10),
+                ("cache miss", "new", 5),
+            ]
+        );
+        let (status, _) = get("/api/diff?from=2022-02&to=2022-03");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn unknown_paths_and_methods_fail() {
+        assert_eq!(
+            get("/api/reports/2022-04/patterns").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(get("/api/nothing").0, StatusCode::NOT_FOUND);
+        assert_eq!(get("/api/reports/%FF/patterns").0, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            request(Method::POST, "/api/reports").0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(get("/").0, StatusCode::OK);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::SourceConfig;
+use std::{env, path::PathBuf, process::Command};
+
+/// A file and line that logged a sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u32,
+}
+
+/// Extract the source location of `rawlog` as configured in `config`.
+///
+/// The configured field is matched against the path regex, and the captured
+/// file is resolved against the checkout root of the sample's app.
+pub fn locate(rawlog: &str, config: &SourceConfig) -> Option<SourceLocation> {
+    let json: serde_json::Value = serde_json::from_str(rawlog).ok()?;
+    let field = json.get(&config.field)?.as_str()?;
+    let captures = config.pattern.captures(field)?;
+    let file = PathBuf::from(captures.name("file")?.as_str());
+    let line = captures.name("line")?.as_str().parse().ok()?;
+
+    let root = json
+        .get("app")
+        .and_then(|app| app.as_str())
+        .and_then(|app| config.roots.get(app))
+        .or(config.root.as_ref());
+    let file = match root {
+        Some(root) if file.is_relative() => root.join(file),
+        _ => file,
+    };
+    Some(SourceLocation { file, line })
+}
+
+/// Command opening `location` in the configured editor
+pub fn editor_command(location: &SourceLocation, config: &SourceConfig) -> Command {
+    let template = config.editor.clone().unwrap_or_else(|| {
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+        format!("{editor} +{{line}} {{file}}")
+    });
+    let file = location.file.to_string_lossy();
+    let line = location.line.to_string();
+    let mut args = template
+        .split_whitespace()
+        .map(|arg| arg.replace("{file}", &file).replace("{line}", &line));
+
+    let mut command = Command::new(args.next().unwrap_or_else(|| "vi".to_string()));
+    command.args(args);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn caller_is_resolved_against_the_root_of_its_app() {
+        let config: Config = toml::from_str(
+            r#"
+            [source.roots]
+            numbers = "/src/numbers"
+            "#,
+        )
+        .unwrap();
+        let rawlog = r#"{"app":"numbers","caller":"handler/numbers.go:31"}"#;
+        assert_eq!(
+            locate(rawlog, &config.source),
+            Some(SourceLocation {
+                file: PathBuf::from("/src/numbers/handler/numbers.go"),
+                line: 31,
+            })
+        );
+        assert_eq!(locate(r#"{"caller":"numbers.go"}"#, &config.source), None);
+    }
+
+    #[test]
+    fn invalid_patterns_fail_to_load() {
+        let config = toml::from_str::<Config>("[source]\npattern = \"(?P<file>\"");
+        assert!(config.is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::pattern::Pattern;
+use serde_json::Value;
+
+/// A sample found by trace correlation, indexes into `App::patterns`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceMatch {
+    pub pattern: usize,
+    pub sample: usize,
+}
+
+/// Trace ID of `rawlog`, the value of the first of `fields` present
+pub fn trace_id(rawlog: &str, fields: &[String]) -> Option<String> {
+    let json: Value = serde_json::from_str(rawlog).ok()?;
+    fields
+        .iter()
+        .find_map(|field| json.get(field).and_then(field_value))
+}
+
+/// All samples of `patterns` carrying `id` in one of `fields`, ordered by date
+pub fn find(patterns: &[Pattern], fields: &[String], id: &str) -> Vec<TraceMatch> {
+    let mut matches = Vec::new();
+    for (pattern_index, pattern) in patterns.iter().enumerate() {
+        for (sample_index, sample) in pattern.samples().iter().enumerate() {
+            // cheap check before parsing the rawlog
+            if !sample.rawlog.contains(id) {
+                continue;
+            }
+            let json: Value = match serde_json::from_str(&sample.rawlog) {
+                Ok(json) => json,
+                Err(_) => continue,
+            };
+            let found = fields
+                .iter()
+                .any(|field| json.get(field).and_then(field_value).as_deref() == Some(id));
+            if found {
+                matches.push(TraceMatch {
+                    pattern: pattern_index,
+                    sample: sample_index,
+                });
+            }
+        }
+    }
+    matches.sort_by_key(|m| patterns[m.pattern].samples()[m.sample].date);
+    matches
+}
+
+/// Trace IDs are usually strings, but numbers are accepted too
+fn field_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TraceConfig;
+
+    #[test]
+    fn span_and_parent_ids_are_not_trace_ids() {
+        let fields = TraceConfig::default().fields;
+        let root = r#"{"xxttxx":"1ef30e841787673d","xxssxx":"1ef30e841787673d","x-parent-id":"0000000000000000"}"#;
+        assert_eq!(trace_id(root, &fields).as_deref(), Some("1ef30e841787673d"));
+        let untraced = r#"{"xxssxx":"30692a5bfb9b41c5","x-parent-id":"0000000000000000"}"#;
+        assert_eq!(trace_id(untraced, &fields), None);
+    }
+
+    #[test]
+    fn numbers_are_ids_and_empty_strings_are_not() {
+        let fields = vec!["trace".to_string()];
+        assert_eq!(trace_id(r#"{"trace":42}"#, &fields).as_deref(), Some("42"));
+        assert_eq!(trace_id(r#"{"trace":""}"#, &fields), None);
+    }
+}
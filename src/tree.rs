@@ -0,0 +1,193 @@
+use crate::{config::SourceConfig, pattern::Pattern};
+use regex::Regex;
+use serde_json::Value;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+use tui::widgets::TableState;
+
+/// Label of the node of patterns whose samples lack the field
+const MISSING: &str = "(none)";
+/// Separates the labels of a node path in its key
+const KEY_SEPARATOR: char = '\u{1f}';
+
+/// Row of the tree, either a field value or a pattern
+#[derive(Clone, Debug)]
+pub struct TreeRow {
+    pub depth: usize,
+    pub label: String,
+    pub count: usize,
+    pub percent: f32,
+    /// Patterns under the node, 1 for a pattern
+    pub patterns: usize,
+    /// Index into the patterns of a pattern row, `None` for a field value
+    pub pattern: Option<usize>,
+    pub expanded: bool,
+    /// Labels of the path to the node, to remember it expanded
+    key: String,
+}
+
+/// Patterns as a tree by the values of sample fields, e.g. by `caller`
+/// file then `level`, each node summing the counts below it
+pub struct TreeView {
+    pub fields: Vec<String>,
+    pub rows: Vec<TreeRow>,
+    pub state: TableState,
+    /// First row on screen, kept by the ui
+    pub offset: usize,
+    expanded: HashSet<String>,
+    /// Value of each field per pattern, computed once per pattern
+    values: Vec<Vec<String>>,
+    /// Source field, its values are reduced to the file of the location
+    source_field: String,
+    source_pattern: Regex,
+}
+
+impl TreeView {
+    pub fn new(fields: Vec<String>, source: &SourceConfig) -> TreeView {
+        TreeView {
+            fields,
+            rows: Vec::new(),
+            state: TableState::default(),
+            offset: 0,
+            expanded: HashSet::new(),
+            values: Vec::new(),
+            source_field: source.field.clone(),
+            source_pattern: source.pattern.clone(),
+        }
+    }
+
+    /// Rebuild the rows from the patterns at `visible` indexes, keeping the
+    /// selected node selected
+    pub fn rebuild(&mut self, patterns: &[Pattern], visible: &[usize]) {
+        for pattern in &patterns[self.values.len()..] {
+            let values = self
+                .fields
+                .iter()
+                .map(|field| self.value(pattern, field))
+                .collect();
+            self.values.push(values);
+        }
+        let selected = self.selected().map(|row| row.key.clone());
+
+        let mut rows = Vec::new();
+        self.add_rows(&mut rows, patterns, visible.to_vec(), 0, "");
+        self.rows = rows;
+
+        let row = selected
+            .and_then(|key| self.rows.iter().position(|row| row.key == key))
+            .unwrap_or(0);
+        self.state.select(if self.rows.is_empty() {
+            None
+        } else {
+            Some(row)
+        });
+    }
+
+    fn add_rows(
+        &self,
+        rows: &mut Vec<TreeRow>,
+        patterns: &[Pattern],
+        mut members: Vec<usize>,
+        depth: usize,
+        parent: &str,
+    ) {
+        if depth == self.fields.len() {
+            members.sort_by_key(|&i| (Reverse(patterns[i].count), i));
+            for index in members {
+                let pattern = &patterns[index];
+                rows.push(TreeRow {
+                    depth,
+                    label: pattern.patterns.clone(),
+                    count: pattern.count,
+                    percent: pattern.percent.unwrap_or(0.0),
+                    patterns: 1,
+                    pattern: Some(index),
+                    expanded: false,
+                    key: format!("{parent}{KEY_SEPARATOR}{index}"),
+                });
+            }
+            return;
+        }
+
+        let mut nodes: HashMap<&str, Vec<usize>> = HashMap::new();
+        for index in members {
+            nodes
+                .entry(&self.values[index][depth])
+                .or_default()
+                .push(index);
+        }
+        let count = |members: &[usize]| members.iter().map(|&i| patterns[i].count).sum::<usize>();
+        let mut nodes: Vec<(&str, Vec<usize>)> = nodes.into_iter().collect();
+        nodes.sort_by_key(|(label, members)| (Reverse(count(members)), *label));
+        for (label, members) in nodes {
+            let key = format!("{parent}{KEY_SEPARATOR}{label}");
+            let expanded = self.expanded.contains(&key);
+            rows.push(TreeRow {
+                depth,
+                label: label.to_string(),
+                count: count(&members),
+                percent: members
+                    .iter()
+                    .map(|&i| patterns[i].percent.unwrap_or(0.0))
+                    .sum(),
+                patterns: members.len(),
+                pattern: None,
+                expanded,
+                key: key.clone(),
+            });
+            if expanded {
+                self.add_rows(rows, patterns, members, depth + 1, &key);
+            }
+        }
+    }
+
+    /// Most common value of `field` in the samples of `pattern`
+    fn value(&self, pattern: &Pattern, field: &str) -> String {
+        let mut values: HashMap<String, usize> = HashMap::new();
+        for sample in pattern.samples() {
+            let json: Value = match serde_json::from_str(&sample.rawlog) {
+                Ok(json) => json,
+                Err(_) => continue,
+            };
+            let value = match field
+                .split('.')
+                .try_fold(&json, |value, key| value.get(key))
+            {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => continue,
+                Some(other) => other.to_string(),
+            };
+            let value = if field == self.source_field {
+                self.source_pattern
+                    .captures(&value)
+                    .and_then(|captures| captures.name("file"))
+                    .map_or(value.clone(), |file| file.as_str().to_string())
+            } else {
+                value
+            };
+            *values.entry(value).or_default() += 1;
+        }
+        values
+            .into_iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+            .map_or_else(|| MISSING.to_string(), |(value, _)| value)
+    }
+
+    pub fn selected(&self) -> Option<&TreeRow> {
+        self.rows.get(self.state.selected()?)
+    }
+
+    /// Expand or collapse the selected node, `false` for a pattern row
+    pub fn toggle_selected(&mut self) -> bool {
+        let key = match self.selected() {
+            Some(row) if row.pattern.is_none() => row.key.clone(),
+            _ => return false,
+        };
+        if !self.expanded.remove(&key) {
+            self.expanded.insert(key);
+        }
+        true
+    }
+}
@@ -1,18 +1,47 @@
-use crate::app::App;
+use crate::{
+    app::App,
+    loader::{LoadProgress, LoadStage},
+    pattern::{Pattern, ReportMeta, Sample},
+    tree::TreeRow,
+    view::Column,
+};
+use std::{borrow::Cow, ops::Range};
+use unicode_width::UnicodeWidthStr;
 
 use tui::{
     backend::Backend,
     layout::Rect,
-    layout::{Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Margin},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table, Tabs, Wrap},
+    widgets::{
+        Axis, Block, BorderType, Borders, Cell, Chart, Clear, Dataset, Gauge, GraphType, Paragraph,
+        Row, Table, TableState, Tabs, Wrap,
+    },
     Frame,
 };
 
 pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    // welcome page until the first patterns arrive
+    if app.pattern_rows.is_empty() && (app.loading.is_some() || app.load_error.is_some()) {
+        draw_home(f, app);
+        return;
+    }
+
+    let command_height = if app.command.is_some() || app.status.is_some() || app.loading.is_some() {
+        1
+    } else {
+        0
+    };
     let chunks = Layout::default()
-        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(command_height),
+            ]
+            .as_ref(),
+        )
         .split(f.size());
     let titles = app
         .tabs
@@ -20,8 +49,16 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         .iter()
         .map(|t| Spans::from(Span::styled(*t, Style::default().fg(Color::Green))))
         .collect();
+    let mut title = app.title.to_string();
+    for meta in &app.reports {
+        title.push_str(" | ");
+        title.push_str(&report_label(meta));
+    }
+    if app.reveal {
+        title.push_str(" | REVEALED");
+    }
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title(app.title))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .highlight_style(
             Style::default()
                 .bg(Color::Yellow)
@@ -35,8 +72,181 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         0 => draw_patterns(f, app, main),
         1 => draw_samples(f, app, main),
         2 => draw_details(f, app, main),
+        3 => draw_trace(f, app, main),
         _ => {}
     };
+    if app.view_menu.is_some() {
+        draw_view_menu(f, app, main);
+    }
+    if let Some(command) = &app.command {
+        f.render_widget(Paragraph::new(format!(":{}", command)), chunks[2]);
+    } else if let Some(status) = &app.status {
+        f.render_widget(Paragraph::new(status.as_str()), chunks[2]);
+    } else if let Some(progress) = &app.loading {
+        f.render_widget(render_progress(progress, app.patterns.len()), chunks[2]);
+    }
+}
+
+/// Menu of the saved views, centered over `area`
+fn draw_view_menu<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    let state = match &mut app.view_menu {
+        Some(state) => state,
+        None => return,
+    };
+    let views = &app.saved.views;
+    let width = area.width.min(60);
+    let height = area.height.min(views.len() as u16 + 2);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let rows = views.iter().map(|view| {
+        let filter = view.filter.as_deref().unwrap_or("");
+        Row::new(vec![
+            Cell::from(Span::raw(view.name.clone())),
+            Cell::from(Span::raw(filter.to_string())),
+        ])
+    });
+    let table = Table::new(rows)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Views (Enter to apply, Esc to close)")
+                .border_type(BorderType::Plain),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .widths(&[Constraint::Percentage(35), Constraint::Percentage(65)]);
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(table, popup, state);
+}
+
+fn draw_home<B>(f: &mut Frame<B>, app: &App)
+where
+    B: Backend,
+{
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(f.size());
+
+    let (state, hint) = match (&app.loading, &app.load_error) {
+        (Some(progress), _) => (
+            Span::raw(stage_label(progress)),
+            "Press 'Esc' to cancel, 'q' to quit",
+        ),
+        (None, Some(error)) => (
+            Span::styled(
+                format!("Failed to load report: {error}"),
+                Style::default().fg(Color::Red),
+            ),
+            "Press 'q' to quit",
+        ),
+        (None, None) => (Span::raw(""), ""),
+    };
+    let home = Paragraph::new(vec![
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw("Welcome")]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw("to")]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled(
+            app.title,
+            Style::default().fg(Color::LightBlue),
+        )]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![state]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(hint)]),
+    ])
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true })
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title("Home")
+            .border_type(BorderType::Plain),
+    );
+    f.render_widget(home, chunks[0]);
+    if let Some(progress) = &app.loading {
+        f.render_widget(render_progress(progress, 0), chunks[1]);
+    }
+}
+
+/// Short description of a report from its envelope metadata
+fn report_label(meta: &ReportMeta) -> String {
+    let mut parts = Vec::new();
+    match (&meta.namespace, &meta.app) {
+        (Some(namespace), Some(app)) => parts.push(format!("{}/{}", namespace, app)),
+        (Some(name), None) | (None, Some(name)) => parts.push(name.clone()),
+        (None, None) => {}
+    }
+    if let Some(period) = &meta.period {
+        parts.push(period.clone());
+    }
+    if let Some(total_lines) = meta.total_lines {
+        parts.push(format!("{} lines", total_lines));
+    }
+    if let Some(generator) = &meta.generator {
+        parts.push(format!("by {}", generator));
+    }
+    if parts.is_empty() {
+        format!("schema v{}", meta.schema_version)
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// What the loader is doing, e.g. `Downloading 1024 / 2048 KiB`
+fn stage_label(progress: &LoadProgress) -> String {
+    let verb = match progress.stage {
+        LoadStage::Connecting => return "Connecting ...".to_string(),
+        LoadStage::Downloading => "Downloading",
+        LoadStage::Parsing => "Parsing",
+    };
+    let verb = if progress.parts > 1 {
+        format!("{verb} report {}/{}", progress.part, progress.parts)
+    } else {
+        verb.to_string()
+    };
+    match progress.total {
+        Some(total) => format!("{verb} {} / {} KiB", progress.read / 1024, total / 1024),
+        None => format!("{verb} {} KiB", progress.read / 1024),
+    }
+}
+
+/// Rows of a bordered table with a header that fit into `area`
+fn table_page_size(area: Rect) -> usize {
+    area.height.saturating_sub(3).max(1) as usize
+}
+
+/// Number of lines `text` takes when word wrapped to `width` columns
+fn wrapped_height(text: &str, width: u16) -> usize {
+    let width = width.max(1) as usize;
+    let mut height = 0;
+    for line in text.lines() {
+        height += 1;
+        let mut current = 0;
+        for word in line.split_inclusive(' ') {
+            let word_width = word.width();
+            if current > 0 && current + word.trim_end().width() > width {
+                height += 1;
+                current = 0;
+            }
+            current += word_width;
+            while current > width {
+                height += 1;
+                current -= width;
+            }
+        }
+    }
+    height
 }
 
 fn draw_patterns<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
@@ -48,10 +258,194 @@ where
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
         .split(area);
 
-    let (pattern, sample) = render_patterns(app);
-    // split horizontal of right rect
-    f.render_stateful_widget(pattern, apps_chunks[0], &mut app.pattern_table_state);
-    f.render_widget(sample, apps_chunks[1]);
+    app.pattern_page_size = table_page_size(apps_chunks[0]);
+    if app.pattern_rows.is_empty() {
+        let message = if app.loading.is_some() {
+            "Loading report ..."
+        } else if app.filter.is_some() {
+            "No pattern matches the filter"
+        } else {
+            "This report contains no patterns"
+        };
+        f.render_widget(empty_state("Patterns", message), area);
+        return;
+    }
+
+    let (rows, mut state) = visible_rows(
+        &app.pattern_table_state,
+        &mut app.pattern_table_offset,
+        app.pattern_rows.len(),
+        app.pattern_page_size,
+    );
+    let title = match &app.filter {
+        Some(filter) => format!(
+            "Patterns ({} of {} match {})",
+            app.matching_patterns,
+            app.patterns.len(),
+            filter
+        ),
+        None => "Patterns".to_string(),
+    };
+    let total_cost = match &app.cost {
+        Some(model) => format!(" | total {}/month", model.format(app.total_cost)),
+        None => String::new(),
+    };
+    match &mut app.tree {
+        Some(tree) => {
+            let (rows, mut state) = visible_rows(
+                &tree.state,
+                &mut tree.offset,
+                tree.rows.len(),
+                app.pattern_page_size,
+            );
+            let title = format!("{} by {}{}", title, tree.fields.join(" › "), total_cost);
+            let table = render_tree(&tree.rows[rows], title);
+            f.render_stateful_widget(table, apps_chunks[0], &mut state);
+        }
+        None => {
+            let title = title + &total_cost;
+            let visible = &app.pattern_rows[rows];
+            let columns = pattern_columns(app, visible);
+            let widths = pattern_widths(&columns);
+            let pattern = render_patterns(app, visible, &columns, &widths, title);
+            // split horizontal of right rect
+            f.render_stateful_widget(pattern, apps_chunks[0], &mut state);
+        }
+    }
+    if app.show_chart {
+        draw_chart(f, app, apps_chunks[1]);
+        return;
+    }
+    let page_size = table_page_size(apps_chunks[1]);
+    match app.current_pattern().map(|p| (p, p.try_samples())) {
+        Some((current, Ok(samples))) if !app.sample_rows.is_empty() => {
+            let rows = &app.sample_rows[..page_size.min(app.sample_rows.len())];
+            let samples: Vec<&Sample> = rows.iter().map(|&i| &samples[i]).collect();
+            let table = render_sample_table(current, &samples, app);
+            f.render_widget(table, apps_chunks[1]);
+        }
+        Some((_, Err(e))) => f.render_widget(
+            empty_state("Samples", format!("Samples could not be decoded: {e}")),
+            apps_chunks[1],
+        ),
+        Some((_, Ok(samples))) if !samples.is_empty() => f.render_widget(
+            empty_state("Samples", "No sample matches the filter"),
+            apps_chunks[1],
+        ),
+        _ => f.render_widget(
+            empty_state("Samples", "This pattern has no samples"),
+            apps_chunks[1],
+        ),
+    }
+}
+
+/// Shares of log volume the cumulative chart marks
+const VOLUME_SHARES: [f32; 3] = [80.0, 90.0, 99.0];
+
+/// Bars of the patterns with the highest counts, next to the share of all
+/// logs made up by the top patterns
+fn draw_chart<B>(f: &mut Frame<B>, app: &App, area: Rect)
+where
+    B: Backend,
+{
+    if app.ranked_patterns.is_empty() {
+        f.render_widget(empty_state("Volume", "No pattern to chart"), area);
+        return;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(area);
+
+    let top = &app.ranked_patterns[..app.config.chart.top.min(app.ranked_patterns.len())];
+    let max = top
+        .iter()
+        .map(|&i| app.patterns[i].count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let inner = chunks[0].inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let count_width = max.to_string().len();
+    let bar_width = usize::from(inner.width / 2).saturating_sub(count_width + 1);
+    let lines: Vec<Spans> = top
+        .iter()
+        .map(|&i| {
+            let pattern = &app.patterns[i];
+            let bar = (pattern.count * bar_width).div_ceil(max);
+            Spans::from(vec![
+                Span::raw(format!("{:>count_width$} ", pattern.count)),
+                Span::styled("█".repeat(bar), Style::default().fg(Color::Yellow)),
+                Span::raw(" ".repeat(bar_width - bar + 1)),
+                Span::raw(pattern.patterns.as_str()),
+            ])
+        })
+        .collect();
+    let bars = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Top {} patterns", top.len())),
+    );
+    f.render_widget(bars, chunks[0]);
+
+    let mut total = 0.0;
+    let cumulative: Vec<(f64, f64)> = app
+        .ranked_patterns
+        .iter()
+        .enumerate()
+        .map(|(rank, &i)| {
+            total += app.patterns[i].percent.unwrap_or(0.0);
+            (rank as f64 + 1.0, f64::from(total))
+        })
+        .collect();
+    let needed: Vec<String> = VOLUME_SHARES
+        .iter()
+        .map(|&share| {
+            match cumulative
+                .iter()
+                .position(|&(_, c)| c >= f64::from(share) - 0.001)
+            {
+                Some(rank) => format!("{}%: {}", share, rank + 1),
+                None => format!("{}%: -", share),
+            }
+        })
+        .collect();
+    let len = cumulative.len() as f64;
+    let thresholds: Vec<[(f64, f64); 2]> = VOLUME_SHARES
+        .iter()
+        .map(|&share| [(1.0, f64::from(share)), (len, f64::from(share))])
+        .collect();
+    let mut datasets = vec![Dataset::default()
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Yellow))
+        .data(&cumulative)];
+    for threshold in &thresholds {
+        datasets.push(
+            Dataset::default()
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::DarkGray))
+                .data(threshold),
+        );
+    }
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Patterns for {}", needed.join(", "))),
+        )
+        .x_axis(
+            Axis::default()
+                .bounds([1.0, len.max(2.0)])
+                .labels(vec![Span::raw("1"), Span::raw(format!("{}", len))]),
+        )
+        .y_axis(Axis::default().bounds([0.0, 100.0]).labels(vec![
+            Span::raw("0%"),
+            Span::raw("50%"),
+            Span::raw("100%"),
+        ]));
+    f.render_widget(chart, chunks[1]);
 }
 
 fn draw_samples<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
@@ -63,23 +457,58 @@ where
         .constraints([Constraint::Percentage(20), Constraint::Percentage(80)].as_ref())
         .split(area);
 
-    let (pattern, sample) = render_samples(app);
+    app.sample_page_size = table_page_size(chunks[1]);
+    let current = match app.current_pattern_index() {
+        Some(index) => &app.patterns[index],
+        None => {
+            f.render_widget(
+                empty_state("Samples", "No pattern selected, the report is empty"),
+                area,
+            );
+            return;
+        }
+    };
+
+    let pattern = render_samples(current);
     // split horizontal of right rect
     f.render_widget(pattern, chunks[0]);
-    f.render_stateful_widget(sample, chunks[1], &mut app.sample_table_state);
+    match current.try_samples() {
+        Ok(samples) if !app.sample_rows.is_empty() => {
+            let (rows, mut state) = visible_rows(
+                &app.sample_table_state,
+                &mut app.sample_table_offset,
+                app.sample_rows.len(),
+                app.sample_page_size,
+            );
+            let samples: Vec<&Sample> =
+                app.sample_rows[rows].iter().map(|&i| &samples[i]).collect();
+            let sample = render_sample_table(current, &samples, app)
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(sample, chunks[1], &mut state);
+        }
+        Ok(samples) if !samples.is_empty() => f.render_widget(
+            empty_state("Samples", "No sample matches the filter"),
+            chunks[1],
+        ),
+        Ok(_) => f.render_widget(
+            empty_state("Samples", "This pattern has no samples"),
+            chunks[1],
+        ),
+        Err(e) => f.render_widget(
+            empty_state("Samples", format!("Samples could not be decoded: {e}")),
+            chunks[1],
+        ),
+    }
 }
 
-fn draw_details<B>(f: &mut Frame<B>, app: &mut App, _area: Rect)
+fn draw_details<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
 where
     B: Backend,
 {
-    let size = f.size();
-
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .margin(1)
         .constraints([Constraint::Percentage(10), Constraint::Percentage(90)].as_ref())
-        .split(size);
+        .split(area);
 
     let create_block = |title| {
         Block::default().borders(Borders::ALL).title(Span::styled(
@@ -93,6 +522,21 @@ where
     //     .block(create_block("Left, no wrap"))
     //     .alignment(Alignment::Left);
     // f.render_widget(paragraph, chunks[0]);
+    if app.current_rawlog.is_empty() {
+        f.render_widget(
+            empty_state("Log sample", "The selected pattern has no samples"),
+            chunks[1],
+        );
+        return;
+    }
+    let inner = chunks[1].inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let content_height = wrapped_height(&app.current_rawlog, inner.width);
+    app.detail_page_size = inner.height.max(1);
+    app.detail_max_scroll = content_height.saturating_sub(inner.height as usize) as u16;
+    app.scroll = app.scroll.min(app.detail_max_scroll);
     let paragraph = Paragraph::new(app.current_rawlog.clone())
         .block(create_block("Log sample"))
         .wrap(Wrap { trim: false })
@@ -176,64 +620,135 @@ where
 //     (pattern_table, sample_detail)
 // }
 
-fn render_samples<'a>(app: &App) -> (Table<'a>, Table<'a>) {
-    let mut patterns = Vec::new();
-    let row = Row::new(vec![
-        Cell::from(Span::raw(format!("{}", app.current_pattern().count))),
-        Cell::from(Span::raw(app.current_pattern().patterns.clone())),
-    ]);
-    patterns.push(row);
+fn draw_trace<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    app.trace_page_size = table_page_size(area);
+    let id = match &app.trace_id {
+        Some(id) => id,
+        None => {
+            f.render_widget(
+                empty_state("Trace", "Press 't' on a sample to follow its trace ID"),
+                area,
+            );
+            return;
+        }
+    };
+
+    let (visible, mut state) = visible_rows(
+        &app.trace_table_state,
+        &mut app.trace_table_offset,
+        app.trace_matches.len(),
+        app.trace_page_size,
+    );
+    let mut rows = Vec::new();
+    for m in &app.trace_matches[visible] {
+        let pattern = &app.patterns[m.pattern];
+        let sample = &pattern.samples()[m.sample];
+        rows.push(Row::new(vec![
+            Cell::from(Span::raw(sample.date.to_string())),
+            Cell::from(Span::raw(pattern.patterns.as_str())),
+            Cell::from(Span::raw(app.redact(&sample.rawlog))),
+        ]));
+    }
 
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-    let pattern_table = Table::new(patterns)
+    let trace_table = Table::new(rows)
         .header(Row::new(vec![
             Cell::from(Span::styled(
-                "Count",
+                "Date",
                 Style::default().add_modifier(Modifier::BOLD),
             )),
             Cell::from(Span::styled(
                 "Pattern",
                 Style::default().add_modifier(Modifier::BOLD),
             )),
+            Cell::from(Span::styled(
+                "log",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
         ]))
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
-                .title("Pattern")
+                .title(format!(
+                    "Trace {} ({} samples)",
+                    id,
+                    app.trace_matches.len()
+                ))
                 .border_type(BorderType::Plain),
         )
         .highlight_style(selected_style)
-        .widths(&[Constraint::Percentage(10), Constraint::Percentage(90)]);
-
-    let samples = app
-        .patterns
-        .get(
-            app.pattern_table_state
-                .selected()
-                .expect("always a selected pattern"),
-        )
-        .unwrap()
-        .samples
-        .clone();
-
-    let mut rows = Vec::new();
-    for sample in samples {
-        let row = Row::new(vec![
-            Cell::from(Span::raw(sample.date.to_string())),
-            Cell::from(Span::raw(sample.rawlog)),
+        .widths(&[
+            Constraint::Percentage(20),
+            Constraint::Percentage(30),
+            Constraint::Percentage(50),
         ]);
-        rows.push(row);
+    f.render_stateful_widget(trace_table, area, &mut state);
+}
+
+/// Rows of a `len` rows table that fit into `height`, scrolled by `offset` so
+/// the selection of `state` stays visible. Only these rows are built, and
+/// they are rendered with the returned state.
+fn visible_rows(
+    state: &TableState,
+    offset: &mut usize,
+    len: usize,
+    height: usize,
+) -> (Range<usize>, TableState) {
+    if let Some(selected) = state.selected() {
+        if selected < *offset {
+            *offset = selected;
+        } else if selected >= *offset + height {
+            *offset = selected + 1 - height;
+        }
     }
+    *offset = (*offset).min(len.saturating_sub(height));
+
+    let mut window = TableState::default();
+    window.select(state.selected().map(|selected| selected - *offset));
+    (*offset..len.min(*offset + height), window)
+}
 
-    let sample_detail = Table::new(rows)
+/// Bordered placeholder shown instead of an empty table
+fn empty_state<'a>(title: &'a str, message: impl Into<Cow<'a, str>>) -> Paragraph<'a> {
+    Paragraph::new(Span::styled(
+        message,
+        Style::default().add_modifier(Modifier::ITALIC),
+    ))
+    .alignment(Alignment::Center)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title(title)
+            .border_type(BorderType::Plain),
+    )
+}
+
+fn render_samples(current: &Pattern) -> Table<'_> {
+    if !current.apps.is_empty() {
+        return render_app_shares(current);
+    }
+
+    let mut patterns = Vec::new();
+    let row = Row::new(vec![
+        Cell::from(Span::raw(format!("{}", current.count))),
+        Cell::from(Span::raw(current.patterns.as_str())),
+    ]);
+    patterns.push(row);
+
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+    Table::new(patterns)
         .header(Row::new(vec![
             Cell::from(Span::styled(
-                "Date",
+                "Count",
                 Style::default().add_modifier(Modifier::BOLD),
             )),
             Cell::from(Span::styled(
-                "log",
+                "Pattern",
                 Style::default().add_modifier(Modifier::BOLD),
             )),
         ]))
@@ -241,29 +756,37 @@ fn render_samples<'a>(app: &App) -> (Table<'a>, Table<'a>) {
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
-                .title("Samples")
+                .title("Pattern")
                 .border_type(BorderType::Plain),
         )
         .highlight_style(selected_style)
-        .widths(&[Constraint::Percentage(20), Constraint::Percentage(80)]);
-
-    (pattern_table, sample_detail)
+        .widths(&[Constraint::Percentage(10), Constraint::Percentage(90)])
 }
 
-fn render_patterns<'a>(app: &App) -> (Table<'a>, Table<'a>) {
-    let mut patterns = Vec::new();
-    for pattern in &app.patterns {
-        let row = Row::new(vec![
-            Cell::from(Span::raw(format!("{}", pattern.count))),
-            Cell::from(Span::raw(format!("{:.2}%", pattern.percent.unwrap_or(0.0)))),
-            Cell::from(Span::raw(pattern.patterns.clone())),
-        ]);
-        patterns.push(row);
+/// The pattern merged from several apps, with the share of each app. The
+/// percent of the total row is global, app rows are percent of the app.
+fn render_app_shares(current: &Pattern) -> Table<'_> {
+    let mut patterns = vec![Row::new(vec![
+        Cell::from(Span::raw("all")),
+        Cell::from(Span::raw(format!("{}", current.count))),
+        Cell::from(Span::raw(format!("{:.2}%", current.percent.unwrap_or(0.0)))),
+        Cell::from(Span::raw(current.patterns.as_str())),
+    ])];
+    for share in &current.apps {
+        patterns.push(Row::new(vec![
+            Cell::from(Span::raw(share.app.as_str())),
+            Cell::from(Span::raw(format!("{}", share.count))),
+            Cell::from(Span::raw(format!("{:.2}%", share.percent.unwrap_or(0.0)))),
+            Cell::from(Span::raw("")),
+        ]));
     }
 
-    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-    let pattern_table = Table::new(patterns)
+    Table::new(patterns)
         .header(Row::new(vec![
+            Cell::from(Span::styled(
+                "App",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
             Cell::from(Span::styled(
                 "Count",
                 Style::default().add_modifier(Modifier::BOLD),
@@ -281,37 +804,34 @@ fn render_patterns<'a>(app: &App) -> (Table<'a>, Table<'a>) {
             Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
-                .title("Patterns")
+                .title("Pattern")
                 .border_type(BorderType::Plain),
         )
-        .highlight_style(selected_style)
         .widths(&[
+            Constraint::Percentage(12),
+            Constraint::Percentage(10),
             Constraint::Percentage(8),
-            Constraint::Percentage(8),
-            Constraint::Percentage(84),
-        ]);
-
-    let samples = app
-        .patterns
-        .get(
-            app.pattern_table_state
-                .selected()
-                .expect("always a selected pattern"),
-        )
-        .unwrap()
-        .samples
-        .clone();
+            Constraint::Percentage(70),
+        ])
+}
 
+/// Rows of `samples` of `pattern`, bookmarked samples marked with a star
+fn render_sample_table<'a>(pattern: &Pattern, samples: &[&'a Sample], app: &App) -> Table<'a> {
     let mut rows = Vec::new();
     for sample in samples {
+        let date = if app.saved.bookmarks.has_sample(pattern, sample) {
+            format!("★ {}", sample.date)
+        } else {
+            sample.date.to_string()
+        };
         let row = Row::new(vec![
-            Cell::from(Span::raw(sample.date.to_string())),
-            Cell::from(Span::raw(sample.rawlog)),
+            Cell::from(Span::raw(date)),
+            Cell::from(Span::raw(app.redact(&sample.rawlog))),
         ]);
         rows.push(row);
     }
 
-    let sample_detail = Table::new(rows)
+    Table::new(rows)
         .header(Row::new(vec![
             Cell::from(Span::styled(
                 "Date",
@@ -329,7 +849,179 @@ fn render_patterns<'a>(app: &App) -> (Table<'a>, Table<'a>) {
                 .title("Samples")
                 .border_type(BorderType::Plain),
         )
-        .widths(&[Constraint::Percentage(20), Constraint::Percentage(80)]);
+        .widths(&[Constraint::Percentage(20), Constraint::Percentage(80)])
+}
+
+fn render_progress(progress: &LoadProgress, amount_patterns: usize) -> Gauge<'_> {
+    let label = format!("{}, {} patterns", stage_label(progress), amount_patterns);
+    Gauge::default()
+        .gauge_style(Style::default().fg(Color::Green).bg(Color::Black))
+        .ratio(progress.ratio().unwrap_or(0.0))
+        .label(label)
+}
+
+/// Rows of the tree, nodes indented by depth with their pattern count
+fn render_tree(rows: &[TreeRow], title: String) -> Table<'static> {
+    let mut table_rows = Vec::new();
+    for row in rows {
+        let indent = "  ".repeat(row.depth);
+        let label = match row.pattern {
+            Some(_) => format!("{}{}", indent, row.label),
+            None => format!(
+                "{}{} {} ({})",
+                indent,
+                if row.expanded { "▾" } else { "▸" },
+                row.label,
+                row.patterns
+            ),
+        };
+        table_rows.push(Row::new(vec![
+            Cell::from(Span::raw(format!("{}", row.count))),
+            Cell::from(Span::raw(format!("{:.2}%", row.percent))),
+            Cell::from(Span::raw(label)),
+        ]));
+    }
+
+    let header = ["Count", "Percent", "Pattern"].iter().map(|title| {
+        Cell::from(Span::styled(
+            *title,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+    });
+    Table::new(table_rows)
+        .header(Row::new(header))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title)
+                .border_type(BorderType::Plain),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .widths(&[
+            Constraint::Percentage(8),
+            Constraint::Percentage(8),
+            Constraint::Percentage(84),
+        ])
+}
+
+/// Optional columns of the Pattern table shown for `visible` patterns: cost
+/// only with prices, and apps only for patterns merged from several apps
+fn pattern_columns(app: &App, visible: &[usize]) -> Vec<Column> {
+    let merged = visible.iter().any(|&i| !app.patterns[i].apps.is_empty());
+    app.columns
+        .iter()
+        .copied()
+        .filter(|column| match column {
+            Column::Cost => app.cost.is_some(),
+            Column::App => merged,
+            Column::Count | Column::Percent => true,
+        })
+        .collect()
+}
+
+/// Widths of `columns` followed by the pattern text, which takes the rest
+fn pattern_widths(columns: &[Column]) -> Vec<Constraint> {
+    let mut widths: Vec<u16> = columns
+        .iter()
+        .map(|column| match column {
+            Column::Count | Column::Percent => 8,
+            Column::Cost => 10,
+            Column::App => 16,
+        })
+        .collect();
+    widths.push(100u16.saturating_sub(widths.iter().sum()));
+    widths.into_iter().map(Constraint::Percentage).collect()
+}
+
+/// Rows of `visible` patterns, a group row showing the totals of its group
+/// and the patterns of expanded groups nested under it
+fn render_patterns<'a>(
+    app: &'a App,
+    visible: &[usize],
+    columns: &[Column],
+    widths: &'a [Constraint],
+    title: String,
+) -> Table<'a> {
+    let all = &app.patterns;
+    let mut patterns = Vec::new();
+    for &index in visible {
+        let pattern = &all[index];
+        let mut mark = String::new();
+        if app.marked_pattern == Some(index) {
+            mark.push_str("* ");
+        }
+        if app.saved.bookmarks.has_pattern(pattern) {
+            mark.push_str("★ ");
+        }
+        let (count, percent, cost, text) = match app.group_rows.get(&index) {
+            Some(group) => (
+                group.count,
+                group.percent,
+                group.cost,
+                format!(
+                    "{}{} [{}] {}",
+                    mark,
+                    if group.expanded { "▾" } else { "▸" },
+                    group.members,
+                    pattern.patterns
+                ),
+            ),
+            None if app.nested_rows.contains(&index) => (
+                pattern.count,
+                pattern.percent.unwrap_or(0.0),
+                app.costs[index],
+                format!("{}  └ {}", mark, pattern.patterns),
+            ),
+            None => (
+                pattern.count,
+                pattern.percent.unwrap_or(0.0),
+                app.costs[index],
+                format!("{}{}", mark, pattern.patterns),
+            ),
+        };
+        let mut cells: Vec<Cell> = columns
+            .iter()
+            .map(|column| {
+                let text = match column {
+                    Column::Count => format!("{}", count),
+                    Column::Percent => format!("{:.2}%", percent),
+                    Column::Cost => app.cost.as_ref().map_or(String::new(), |m| m.format(cost)),
+                    Column::App => pattern.app_names(),
+                };
+                Cell::from(Span::raw(text))
+            })
+            .collect();
+        cells.push(Cell::from(Span::raw(text)));
+        patterns.push(Row::new(cells));
+    }
+
+    let titles = columns
+        .iter()
+        .map(|column| match column {
+            Column::Count => "Count",
+            Column::Percent => "Percent",
+            Column::Cost => "Cost/month",
+            Column::App => "App",
+        })
+        .chain(["Pattern"]);
+    let header = titles.map(|title| {
+        Cell::from(Span::styled(
+            title,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+    });
 
-    (pattern_table, sample_detail)
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+    Table::new(patterns)
+        .header(Row::new(header))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title)
+                .border_type(BorderType::Plain),
+        )
+        .highlight_style(selected_style)
+        .widths(widths)
 }
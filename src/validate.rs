@@ -0,0 +1,403 @@
+use crate::pattern::{Pattern, ReportMeta, Sample, SCHEMA_VERSION};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{value::RawValue, Value};
+use std::{collections::HashMap, fmt};
+
+/// Largest difference between a reported percent and the one computed
+/// from the counts, reports usually round to two decimals
+const PERCENT_TOLERANCE: f32 = 0.01;
+
+/// Bytes per block of the text whose chars are counted ahead, so finding
+/// a column counts at most one block
+const CHAR_BLOCK: usize = 4096;
+
+/// A problem found in a report, at a 1-based line and column
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Check the report `text` for problems, sorted by position. Rawlogs that
+/// look like JSON must parse, with `json_rawlogs` all of them must.
+pub fn validate(text: &str, json_rawlogs: bool) -> Vec<Diagnostic> {
+    let mut validator = Validator::new(text, json_rawlogs);
+    validator.report();
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+struct Validator<'a> {
+    text: &'a str,
+    json_rawlogs: bool,
+    /// Where the envelope's `total_lines` is
+    total_lines_at: Option<&'a str>,
+    diagnostics: Vec<Diagnostic>,
+    /// Offset of the start of each line
+    line_starts: Vec<usize>,
+    /// Chars before each block of [`CHAR_BLOCK`] bytes
+    block_chars: Vec<usize>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(text: &'a str, json_rawlogs: bool) -> Validator<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let mut block_chars = Vec::new();
+        let mut chars = 0;
+        for block in text.as_bytes().chunks(CHAR_BLOCK) {
+            block_chars.push(chars);
+            chars += count_chars(block);
+        }
+        block_chars.push(chars);
+        Validator {
+            text,
+            json_rawlogs,
+            total_lines_at: None,
+            diagnostics: Vec::new(),
+            line_starts,
+            block_chars,
+        }
+    }
+
+    fn report(&mut self) {
+        let (patterns, meta) = match self.patterns() {
+            Some(parsed) => parsed,
+            None => return,
+        };
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut percents = Vec::new();
+        let mut total = 0;
+        for raw in patterns {
+            let raw = raw.get();
+            let fields: HashMap<String, &RawValue> = match serde_json::from_str(raw) {
+                Ok(fields) => fields,
+                Err(e) => {
+                    self.error(raw, &e);
+                    continue;
+                }
+            };
+            let pattern: Pattern = match serde_json::from_str(raw) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    self.error(raw, &e);
+                    continue;
+                }
+            };
+            let field = |name: &str| fields.get(name).map_or(raw, |value| value.get());
+
+            let at = field("patterns");
+            if let Some(first) = seen.get(&pattern.patterns) {
+                self.push(
+                    at,
+                    format!("duplicate pattern, first seen at line {}", first),
+                );
+            } else {
+                let (line, _) = self.position(at);
+                seen.insert(pattern.patterns.clone(), line);
+            }
+
+            let samples = self.samples(field("samples"));
+            for (index, (at, sample)) in samples.iter().enumerate() {
+                self.check_sample(at, index, sample);
+            }
+            if pattern.count < samples.len() {
+                self.push(
+                    field("count"),
+                    format!(
+                        "count {} is lower than the {} samples",
+                        pattern.count,
+                        samples.len()
+                    ),
+                );
+            }
+
+            total += pattern.count;
+            if let Some(percent) = pattern.percent {
+                percents.push((field("percent"), percent, pattern.count));
+            }
+        }
+
+        let total_lines = meta.as_ref().and_then(|meta| meta.total_lines);
+        if let Some(total_lines) = total_lines {
+            if total_lines < total as u64 {
+                self.push(
+                    self.total_lines_at.unwrap_or(self.text),
+                    format!(
+                        "total_lines {} is lower than the sum of pattern counts {}",
+                        total_lines, total
+                    ),
+                );
+            }
+        }
+        let base = total_lines.unwrap_or(total as u64);
+        for (at, percent, count) in percents {
+            if !(0.0..=100.0).contains(&percent) {
+                self.push(at, format!("percent {} is not between 0 and 100", percent));
+                continue;
+            }
+            let expected = if base == 0 {
+                0.0
+            } else {
+                count as f32 / base as f32 * 100.0
+            };
+            if (percent - expected).abs() > PERCENT_TOLERANCE {
+                self.push(
+                    at,
+                    format!(
+                        "percent {:.2} doesn't match count {}, expected {:.2}",
+                        percent, count, expected
+                    ),
+                );
+            }
+        }
+    }
+
+    /// The patterns of a bare array or envelope report, with the metadata
+    /// of the envelope
+    fn patterns(&mut self) -> Option<(Vec<&'a RawValue>, Option<ReportMeta>)> {
+        let text = self.text;
+        if !text.trim_start().starts_with('{') {
+            return match serde_json::from_str(text) {
+                Ok(patterns) => Some((patterns, None)),
+                Err(e) => {
+                    self.error(text, &e);
+                    None
+                }
+            };
+        }
+
+        let fields: HashMap<String, &RawValue> = match serde_json::from_str(text) {
+            Ok(fields) => fields,
+            Err(e) => {
+                self.error(text, &e);
+                return None;
+            }
+        };
+        self.total_lines_at = fields.get("total_lines").map(|v| v.get());
+        let meta = match serde_json::from_str::<ReportMeta>(text) {
+            Ok(meta) => {
+                if meta.schema_version > SCHEMA_VERSION {
+                    let at = fields.get("schema_version").map_or(text, |v| v.get());
+                    self.push(
+                        at,
+                        format!(
+                            "unsupported schema_version {}, expected at most {}",
+                            meta.schema_version, SCHEMA_VERSION
+                        ),
+                    );
+                }
+                Some(meta)
+            }
+            Err(e) => {
+                self.error(text, &e);
+                None
+            }
+        };
+        let patterns = match fields.get("patterns") {
+            Some(patterns) => patterns.get(),
+            None => {
+                self.push(text, "missing field `patterns`");
+                return None;
+            }
+        };
+        match serde_json::from_str(patterns) {
+            Ok(patterns) => Some((patterns, meta)),
+            Err(e) => {
+                self.error(patterns, &e);
+                None
+            }
+        }
+    }
+
+    /// Samples of a pattern with where each one is. Samples in the
+    /// stringified form are all placed at the string.
+    fn samples(&mut self, at: &'a str) -> Vec<(&'a str, Value)> {
+        if at.starts_with('[') {
+            let samples: Vec<&RawValue> = match serde_json::from_str(at) {
+                Ok(samples) => samples,
+                Err(e) => {
+                    self.error(at, &e);
+                    return Vec::new();
+                }
+            };
+            return samples
+                .into_iter()
+                .filter_map(|raw| match serde_json::from_str(raw.get()) {
+                    Ok(value) => Some((raw.get(), value)),
+                    Err(e) => {
+                        self.error(raw.get(), &e);
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        let text: String = match serde_json::from_str(at) {
+            Ok(text) => text,
+            Err(e) => {
+                self.error(at, &e);
+                return Vec::new();
+            }
+        };
+        match serde_json::from_str::<Vec<Value>>(&text) {
+            Ok(samples) => samples.into_iter().map(|value| (at, value)).collect(),
+            Err(e) => {
+                self.push(at, format!("samples are not a JSON array: {}", message(&e)));
+                Vec::new()
+            }
+        }
+    }
+
+    fn check_sample(&mut self, at: &str, index: usize, sample: &Value) {
+        if let Some(rawlog) = sample.get("rawlog").and_then(Value::as_str) {
+            let expected = self.json_rawlogs || rawlog.trim_start().starts_with('{');
+            if expected && !serde_json::from_str::<Value>(rawlog).is_ok_and(|v| v.is_object()) {
+                self.push(at, format!("sample {}: rawlog is not a JSON object", index));
+            }
+        }
+        if let Some(date) = sample.get("date").and_then(Value::as_str) {
+            if let Err(e) = date.parse::<DateTime<Utc>>() {
+                self.push(
+                    at,
+                    format!("sample {}: unparseable date `{}`: {}", index, date, e),
+                );
+                return;
+            }
+        }
+        if let Err(e) = Sample::deserialize(sample) {
+            self.push(at, format!("sample {}: {}", index, message(&e)));
+        }
+    }
+
+    /// Report a parse error of `part`, whose position is relative to it
+    fn error(&mut self, part: &str, error: &serde_json::Error) {
+        // serde_json counts columns in bytes
+        let column = error.column().saturating_sub(1);
+        let offset = if error.line() <= 1 {
+            self.offset(part) + column
+        } else {
+            let line = self.line(self.offset(part)) + error.line() - 1;
+            self.line_starts
+                .get(line)
+                .map_or(self.text.len(), |&start| start + column)
+        };
+        let (line, column) = self.position_at(offset.min(self.text.len()));
+        self.diagnostics.push(Diagnostic {
+            line,
+            column,
+            message: message(error),
+        });
+    }
+
+    fn push(&mut self, at: &str, message: impl Into<String>) {
+        let (line, column) = self.position(at);
+        self.diagnostics.push(Diagnostic {
+            line,
+            column,
+            message: message.into(),
+        });
+    }
+
+    /// Line and column of `part`, a slice of the report text
+    fn position(&self, part: &str) -> (usize, usize) {
+        self.position_at(self.offset(part))
+    }
+
+    /// 1-based line and column, in chars, of the byte `offset`
+    fn position_at(&self, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let column = self.chars_before(offset) - self.chars_before(self.line_starts[line]);
+        (line + 1, column + 1)
+    }
+
+    /// Offset of `part`, a slice of the report text
+    fn offset(&self, part: &str) -> usize {
+        (part.as_ptr() as usize)
+            .saturating_sub(self.text.as_ptr() as usize)
+            .min(self.text.len())
+    }
+
+    /// 0-based line of the byte `offset`
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    fn chars_before(&self, offset: usize) -> usize {
+        let block = offset / CHAR_BLOCK;
+        self.block_chars[block] + count_chars(&self.text.as_bytes()[block * CHAR_BLOCK..offset])
+    }
+}
+
+/// Chars in `bytes`, which may cut a char: only the bytes starting one count
+fn count_chars(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b & 0xC0 != 0x80).count()
+}
+
+/// Message of a serde_json error without its position
+fn message(error: &serde_json::Error) -> String {
+    let mut message = error.to_string();
+    if let Some(i) = message.rfind(" at line ") {
+        message.truncate(i);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(text: &str) -> Vec<String> {
+        validate(text, false)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn columns_count_chars() {
+        let report = r#"[{"patterns":"é","count":"x","percent":null,"samples":"[]"}]"#;
+        assert_eq!(
+            positions(report),
+            ["1:28: invalid type: string \"x\", expected usize"]
+        );
+    }
+
+    #[test]
+    fn errors_on_later_lines_are_placed_on_them() {
+        let report = "[\n  {\"patterns\": \"é\",\n   \"count\": 1,\n   \"percent\": nul}\n]";
+        assert_eq!(positions(report), ["4:18: expected ident"]);
+    }
+
+    #[test]
+    fn duplicates_point_at_the_first_line() {
+        let pattern = r#"{"patterns":"a","count":1,"percent":50.0,"samples":"[]"}"#;
+        let report = format!("[{},\n{}]", pattern, pattern);
+        assert_eq!(
+            positions(&report),
+            ["2:13: duplicate pattern, first seen at line 1"]
+        );
+    }
+
+    #[test]
+    fn positions_past_a_char_block_count_every_char() {
+        let text = "é".repeat(CHAR_BLOCK);
+        let validator = Validator::new(&text, false);
+        assert_eq!(validator.position_at(CHAR_BLOCK * 2), (1, CHAR_BLOCK + 1));
+        assert_eq!(
+            validator.position_at(CHAR_BLOCK + 2),
+            (1, CHAR_BLOCK / 2 + 2)
+        );
+    }
+}
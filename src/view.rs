@@ -0,0 +1,257 @@
+use crate::{
+    app::MenuItem,
+    config::config_dir,
+    error::Error,
+    metrics::pattern_id,
+    pattern::{Pattern, Sample},
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const VIEWS_FILE: &str = "views.toml";
+
+/// Order of the rows of the Pattern table
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// Highest count first
+    #[default]
+    Count,
+    /// Pattern text in alphabetical order
+    Pattern,
+    /// Highest estimated cost first
+    Cost,
+}
+
+impl SortKey {
+    pub fn parse(name: &str) -> Option<SortKey> {
+        match name {
+            "count" => Some(SortKey::Count),
+            "pattern" => Some(SortKey::Pattern),
+            "cost" => Some(SortKey::Cost),
+            _ => None,
+        }
+    }
+}
+
+/// Optional column of the Pattern table, the pattern text is always shown
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Count,
+    Percent,
+    /// Estimated cost, when prices are configured
+    Cost,
+    /// Apps of merged patterns, when several apps are loaded
+    App,
+}
+
+impl Column {
+    pub const ALL: [Column; 4] = [Column::Count, Column::Percent, Column::Cost, Column::App];
+
+    pub fn parse(name: &str) -> Option<Column> {
+        Column::ALL
+            .iter()
+            .copied()
+            .find(|column| column.name() == name)
+    }
+
+    /// Parse the column names separated by spaces, keeping the first of
+    /// repeated ones
+    pub fn parse_list(names: &str) -> Option<Vec<Column>> {
+        let columns: Option<Vec<Column>> = names.split_whitespace().map(Column::parse).collect();
+        columns.map(unique_columns)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Count => "count",
+            Column::Percent => "percent",
+            Column::Cost => "cost",
+            Column::App => "app",
+        }
+    }
+}
+
+/// Filter, sort, columns and tab saved under a name
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct View {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default = "all_columns", deserialize_with = "deserialize_columns")]
+    pub columns: Vec<Column>,
+    #[serde(default = "pattern_tab")]
+    pub tab: MenuItem,
+}
+
+fn all_columns() -> Vec<Column> {
+    Column::ALL.to_vec()
+}
+
+fn unique_columns(columns: Vec<Column>) -> Vec<Column> {
+    let mut unique = Vec::with_capacity(columns.len());
+    for column in columns {
+        if !unique.contains(&column) {
+            unique.push(column);
+        }
+    }
+    unique
+}
+
+fn deserialize_columns<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Column>, D::Error> {
+    Vec::deserialize(deserializer).map(unique_columns)
+}
+
+fn pattern_tab() -> MenuItem {
+    MenuItem::Pattern
+}
+
+/// Bookmarked sample, by the id of its pattern and of its rawlog
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SampleBookmark {
+    pub pattern: String,
+    pub sample: String,
+}
+
+/// Patterns and samples bookmarked, by hashes of their text so they are
+/// found again in later reports
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Bookmarks {
+    pub patterns: Vec<String>,
+    pub samples: Vec<SampleBookmark>,
+}
+
+impl Bookmarks {
+    pub fn has_pattern(&self, pattern: &Pattern) -> bool {
+        self.patterns.contains(&pattern_id(&pattern.patterns))
+    }
+
+    pub fn has_sample(&self, pattern: &Pattern, sample: &Sample) -> bool {
+        self.samples.contains(&sample_bookmark(pattern, sample))
+    }
+
+    /// Bookmark `pattern`, or remove its bookmark. `true` when bookmarked.
+    pub fn toggle_pattern(&mut self, pattern: &Pattern) -> bool {
+        let id = pattern_id(&pattern.patterns);
+        match self.patterns.iter().position(|p| *p == id) {
+            Some(i) => {
+                self.patterns.remove(i);
+                false
+            }
+            None => {
+                self.patterns.push(id);
+                true
+            }
+        }
+    }
+
+    /// Bookmark `sample`, or remove its bookmark. `true` when bookmarked.
+    pub fn toggle_sample(&mut self, pattern: &Pattern, sample: &Sample) -> bool {
+        let bookmark = sample_bookmark(pattern, sample);
+        match self.samples.iter().position(|s| *s == bookmark) {
+            Some(i) => {
+                self.samples.remove(i);
+                false
+            }
+            None => {
+                self.samples.push(bookmark);
+                true
+            }
+        }
+    }
+}
+
+fn sample_bookmark(pattern: &Pattern, sample: &Sample) -> SampleBookmark {
+    SampleBookmark {
+        pattern: pattern_id(&pattern.patterns),
+        sample: pattern_id(&sample.rawlog),
+    }
+}
+
+/// Views and bookmarks kept in `views.toml` in the config dir
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Saved {
+    // before the bookmarks table, toml has no values after tables
+    #[serde(rename = "view")]
+    pub views: Vec<View>,
+    pub bookmarks: Bookmarks,
+}
+
+impl Saved {
+    /// `views.toml` in the config dir
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(VIEWS_FILE))
+    }
+
+    /// Load views and bookmarks from `path`, a missing file has none
+    pub fn load(path: &Path) -> Result<Saved, Error> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(Error::ParseViewsError),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Saved::default()),
+            Err(e) => Err(Error::ReadViewsError(e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::WriteViewsError)?;
+        }
+        let content = toml::to_string(self).expect("views serialize to toml");
+        fs::write(path, content).map_err(Error::WriteViewsError)
+    }
+
+    pub fn view(&self, name: &str) -> Option<&View> {
+        self.views.iter().find(|view| view.name == name)
+    }
+
+    /// Add `view`, replacing the view of the same name
+    pub fn put_view(&mut self, view: View) {
+        match self.views.iter_mut().find(|v| v.name == view.name) {
+            Some(existing) => *existing = view,
+            None => self.views.push(view),
+        }
+    }
+
+    /// Remove the view called `name`, `false` when there is none
+    pub fn remove_view(&mut self, name: &str) -> bool {
+        let len = self.views.len();
+        self.views.retain(|view| view.name != name);
+        self.views.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_columns_are_shown_once() {
+        assert_eq!(
+            Column::parse_list("app count app count"),
+            Some(vec![Column::App, Column::Count])
+        );
+        assert_eq!(Column::parse_list("count size"), None);
+    }
+
+    #[test]
+    fn loaded_views_drop_repeated_columns() {
+        let view: View = toml::from_str(
+            r#"
+            name = "apps"
+            columns = ["app", "app", "cost", "app"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(view.columns, vec![Column::App, Column::Cost]);
+    }
+}
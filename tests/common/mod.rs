@@ -0,0 +1,19 @@
+//! Fixtures shared by the integration tests, each test file uses only some
+#![allow(dead_code)]
+
+use log_pattern_viewer::{app::App, config::Config, loader::read_report_file};
+use std::path::PathBuf;
+
+pub fn manifest_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+pub fn app_with(config: Config) -> App<'static> {
+    let report = manifest_path("samples/report.json");
+    let patterns = read_report_file(report.to_str().unwrap()).expect("sample report parses");
+    App::new("Log Pattern Viewer", patterns, config)
+}
+
+pub fn app() -> App<'static> {
+    app_with(Config::default())
+}
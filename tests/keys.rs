@@ -0,0 +1,136 @@
+mod common;
+
+use common::app;
+use crossterm::event::{KeyCode, KeyEvent};
+use log_pattern_viewer::{
+    app::{Action, KeyOutcome, Keymap, MenuItem, Scope},
+    query::Query,
+};
+
+#[test]
+fn tab_bindings_win_over_global_ones() {
+    let keymap = Keymap::default();
+    let down = KeyCode::Char('j');
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Pattern), down),
+        Some(Action::NextPattern)
+    );
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Details), down),
+        Some(Action::ScrollDown)
+    );
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Trace), KeyCode::Enter),
+        Some(Action::OpenTraceMatch)
+    );
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Samples), KeyCode::Enter),
+        None
+    );
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Trace), KeyCode::Char('q')),
+        Some(Action::Quit)
+    );
+}
+
+#[test]
+fn unbound_keys_do_nothing() {
+    let keymap = Keymap::default();
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Pattern), KeyCode::Char('a')),
+        None
+    );
+    assert_eq!(
+        keymap.action(Scope::Tab(MenuItem::Pattern), KeyCode::Char('d')),
+        None
+    );
+}
+
+#[test]
+fn keys_can_be_remapped() {
+    let mut app = app();
+    app.keymap.bind(
+        Scope::Tab(MenuItem::Pattern),
+        KeyCode::Char('n'),
+        Action::NextPattern,
+    );
+    app.keymap.unbind(Scope::Tabs, KeyCode::Char('q'));
+    let key = |c| KeyEvent::from(KeyCode::Char(c));
+    assert_eq!(app.handle_key(key('q')), KeyOutcome::Handled);
+    app.handle_key(key('n'));
+    assert_eq!(app.pattern_table_state.selected(), Some(1));
+}
+
+#[test]
+fn command_line_and_view_menu_keys_are_bound() {
+    let keymap = Keymap::default();
+    assert_eq!(
+        keymap.action(Scope::Command, KeyCode::Enter),
+        Some(Action::RunCommand)
+    );
+    assert_eq!(keymap.action(Scope::Command, KeyCode::Char('q')), None);
+    assert_eq!(
+        keymap.action(Scope::ViewMenu, KeyCode::Char('j')),
+        Some(Action::NextView)
+    );
+    assert_eq!(
+        keymap.action(Scope::ViewMenu, KeyCode::Char('q')),
+        Some(Action::CloseViewMenu)
+    );
+
+    let mut app = app();
+    app.keymap
+        .bind(Scope::Command, KeyCode::Tab, Action::CancelCommand);
+    let key = |code| KeyEvent::from(code);
+    app.handle_key(key(KeyCode::Char(':')));
+    for c in "sve".chars() {
+        app.handle_key(key(KeyCode::Char(c)));
+    }
+    app.handle_key(key(KeyCode::Backspace));
+    assert_eq!(app.command.as_deref(), Some("sv"));
+    app.handle_key(key(KeyCode::Tab));
+    assert_eq!(app.command, None);
+}
+
+#[test]
+fn recorded_actions_replay_like_keys() {
+    let keys = [KeyCode::Char('j'), KeyCode::Char('l'), KeyCode::Down];
+    let mut pressed = app();
+    let mut actions = Vec::new();
+    for key in keys {
+        let scope = pressed.key_scope();
+        actions.extend(pressed.keymap.action(scope, key));
+        pressed.handle_key(KeyEvent::from(key));
+    }
+    assert_eq!(
+        actions,
+        [Action::NextPattern, Action::NextTab, Action::NextSample]
+    );
+
+    let mut replayed = app();
+    for &action in &actions {
+        replayed.apply(action);
+    }
+    assert_eq!(
+        replayed.current_pattern_index(),
+        pressed.current_pattern_index()
+    );
+    assert_eq!(
+        replayed.current_sample_index(),
+        pressed.current_sample_index()
+    );
+    assert_eq!(replayed.tabs.index, pressed.tabs.index);
+}
+
+#[test]
+fn copying_leaves_the_terminal_to_the_caller() {
+    let mut app = app();
+    let copy = KeyEvent::from(KeyCode::Char('y'));
+    let pattern = app.current_pattern().unwrap().patterns.clone();
+    assert_eq!(app.handle_key(copy), KeyOutcome::Copy(pattern));
+    assert_eq!(app.status, None);
+
+    app.set_filter(Some(Query::parse("count < 0").unwrap()));
+    assert_eq!(app.handle_key(copy), KeyOutcome::Handled);
+    assert_eq!(app.status.as_deref(), Some("Nothing to copy"));
+}
@@ -0,0 +1,199 @@
+//! Renders the viewer on `samples/report.json` with `TestBackend` and
+//! compares the screens to `tests/snapshots`. Run with `UPDATE_SNAPSHOTS=1`
+//! to write the snapshots again after a change to the ui.
+
+mod common;
+
+use common::{app, manifest_path};
+use crossterm::event::{KeyCode, KeyEvent};
+use log_pattern_viewer::{
+    app::{App, KeyOutcome},
+    config::Config,
+    query::Query,
+    ui::draw,
+};
+use std::{env, fs};
+use tui::{backend::TestBackend, Terminal};
+
+const WIDTH: u16 = 120;
+const HEIGHT: u16 = 30;
+
+/// Draw `app` and return the screen, one line per row
+fn render(app: &mut App) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    terminal.draw(|f| draw(f, app)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut screen = String::new();
+    for row in buffer.content.chunks(WIDTH as usize) {
+        let line: String = row.iter().map(|cell| cell.symbol.as_str()).collect();
+        screen.push_str(line.trim_end());
+        screen.push('\n');
+    }
+    screen
+}
+
+/// Press each key of `keys`, drawing after each like the terminal does, as
+/// page sizes are taken from the last draw
+fn press(app: &mut App, keys: &[KeyCode]) {
+    for &key in keys {
+        render(app);
+        assert_eq!(app.handle_key(KeyEvent::from(key)), KeyOutcome::Handled);
+    }
+}
+
+fn type_text(app: &mut App, text: &str) {
+    let keys: Vec<KeyCode> = text.chars().map(KeyCode::Char).collect();
+    press(app, &keys);
+}
+
+fn assert_snapshot(name: &str, screen: &str) {
+    let path = manifest_path(&format!("tests/snapshots/{}.txt", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, screen).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no snapshot {}, run with UPDATE_SNAPSHOTS=1", name));
+    assert!(
+        expected == screen,
+        "screen differs from snapshot {}:\n{}",
+        name,
+        screen
+    );
+}
+
+#[test]
+fn pattern_tab() {
+    let mut app = app();
+    assert_snapshot("pattern_tab", &render(&mut app));
+}
+
+#[test]
+fn pattern_tab_after_moving_down() {
+    let mut app = app();
+    type_text(&mut app, "jj");
+    assert_snapshot("pattern_tab_after_moving_down", &render(&mut app));
+}
+
+#[test]
+fn sample_tab() {
+    let mut app = app();
+    type_text(&mut app, "jl");
+    assert_snapshot("sample_tab", &render(&mut app));
+}
+
+#[test]
+fn detail_tab() {
+    let mut app = app();
+    type_text(&mut app, "lj");
+    press(&mut app, &[KeyCode::Right]);
+    assert_snapshot("detail_tab", &render(&mut app));
+}
+
+#[test]
+fn trace_tab() {
+    let mut app = app();
+    type_text(&mut app, "jjjt");
+    assert_snapshot("trace_tab", &render(&mut app));
+}
+
+#[test]
+fn filter_matching_nothing() {
+    let mut app = app();
+    type_text(&mut app, ":filter pattern ~ /no such pattern/");
+    press(&mut app, &[KeyCode::Enter]);
+    assert_snapshot("filter_matching_nothing", &render(&mut app));
+}
+
+#[test]
+fn keys_on_an_empty_table_do_not_panic() {
+    let mut app = app();
+    type_text(&mut app, ":filter count < 0");
+    press(&mut app, &[KeyCode::Enter]);
+    for tab in 0..4 {
+        press(
+            &mut app,
+            &[
+                KeyCode::Down,
+                KeyCode::Up,
+                KeyCode::PageDown,
+                KeyCode::PageUp,
+                KeyCode::End,
+                KeyCode::Home,
+                KeyCode::Enter,
+                KeyCode::Char('d'),
+                KeyCode::Char('t'),
+                KeyCode::Char('z'),
+                KeyCode::Char('b'),
+                KeyCode::Char('\''),
+            ],
+        );
+        if tab < 3 {
+            press(&mut app, &[KeyCode::Right]);
+        }
+    }
+    render(&mut app);
+}
+
+#[test]
+fn moving_past_the_last_row_wraps() {
+    let mut app = app();
+    press(&mut app, &[KeyCode::End, KeyCode::Down]);
+    assert_eq!(app.pattern_table_state.selected(), Some(0));
+    press(&mut app, &[KeyCode::Up]);
+    assert_eq!(
+        app.pattern_table_state.selected(),
+        Some(app.pattern_rows.len() - 1)
+    );
+}
+
+#[test]
+fn jump_past_the_end_selects_the_last_row() {
+    let mut app = app();
+    type_text(&mut app, ":100000");
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        app.pattern_table_state.selected(),
+        Some(app.pattern_rows.len() - 1)
+    );
+}
+
+#[test]
+fn quit() {
+    let mut app = app();
+    let key = KeyEvent::from(KeyCode::Char('q'));
+    assert_eq!(app.handle_key(key), KeyOutcome::Quit);
+}
+
+#[test]
+fn percent_filter_applies_to_streamed_patterns() {
+    let patterns = app().patterns;
+    let mut app = App::new("Log Pattern Viewer", Vec::new(), Config::default());
+    app.set_filter(Some(Query::parse("percent > 5").unwrap()));
+    for batch in patterns.chunks(4) {
+        app.push_patterns(batch.to_vec());
+    }
+    let shown: Vec<f32> = app
+        .pattern_rows
+        .iter()
+        .map(|&i| app.patterns[i].percent.unwrap())
+        .collect();
+    assert_eq!(shown.len(), 4);
+    assert!(shown.iter().all(|&percent| percent > 5.0));
+}
+
+#[test]
+fn sample_selection_survives_a_filter_change() {
+    let mut app = app();
+    type_text(&mut app, "ljj");
+    let selected = app.current_sample_index();
+    let first = &app.current_pattern().unwrap().samples()[0].rawlog;
+    let first: serde_json::Value = serde_json::from_str(first).unwrap();
+    let query = format!("sample.ts != {}", first["ts"]);
+    type_text(&mut app, "hj");
+    app.set_filter(Some(Query::parse(&query).unwrap()));
+    type_text(&mut app, "k");
+    assert_eq!(app.pattern_table_state.selected(), Some(0));
+    assert_eq!(app.current_sample_index(), selected);
+}